@@ -0,0 +1,247 @@
+// accounts.rs
+// Очередь из нескольких аккаунтов: статусы проверяются параллельно, время
+// синхронизируется один раз, а заявки всех аккаунтов уходят к одной полуночи.
+use std::{future::Future, net::SocketAddr, sync::Arc};
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Level, Span, field::Empty, info_span};
+
+use crate::clock::{Clock, SharedClock};
+use crate::deadline::Deadline;
+use crate::engine::{Engine, attempt_span};
+use crate::events::EventSink;
+use crate::history::AttemptRecord;
+use crate::outcome::ApplyOutcome;
+
+/// Аккаунт в очереди.
+#[derive(Debug, Clone)]
+pub struct Account {
+    /// Название для лога и результатов (как у сохраненного токена).
+    pub label: String,
+    pub cookie_value: String,
+    pub device_id: String,
+}
+
+/// Итог заявки одного аккаунта.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountOutcome {
+    pub label: String,
+    pub outcome: ApplyOutcome,
+}
+
+/// Аккаунт с рассчитанным временем отправки и прогретым соединением.
+#[derive(Clone)]
+struct Prepared {
+    account: Account,
+    target_time: DateTime<Tz>,
+    warm_addr: Option<SocketAddr>,
+}
+
+/// Подписывает сообщения движка названием аккаунта.
+struct AccountSink {
+    label: String,
+    inner: Arc<dyn EventSink>,
+}
+
+impl EventSink for AccountSink {
+    fn log(&self, message: &str) {
+        self.inner.log(&format!("[{}] {}", self.label, message));
+    }
+
+    fn event(&self, level: Level, message: &str) {
+        self.inner
+            .event(level, &format!("[{}] {}", self.label, message));
+    }
+
+    fn status(&self, ready: bool, message: &str) {
+        self.inner
+            .status(ready, &format!("{}: {}", self.label, message));
+    }
+
+    fn deadline(&self, title: &str, deadline: &Deadline) {
+        self.inner
+            .deadline(&format!("{}: {}", self.label, title), deadline);
+    }
+
+    fn attempt(&self, record: &AttemptRecord) {
+        self.inner.attempt(record);
+    }
+}
+
+/// Запускает `task` для каждого аккаунта параллельно и возвращает результаты
+/// в порядке аккаунтов. Каждая задача выполняется в span своего аккаунта.
+async fn for_each_account<A, T, F, Fut>(accounts: &[(Engine, A)], task: F) -> Vec<T>
+where
+    A: Clone,
+    F: Fn(Engine, A) -> Fut,
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let handles: Vec<JoinHandle<T>> = accounts
+        .iter()
+        .map(|(engine, account)| {
+            tokio::spawn(task(engine.clone(), account.clone()).instrument(engine.span.clone()))
+        })
+        .collect();
+    let mut results = vec![];
+    for handle in handles {
+        match handle.await {
+            Ok(result) => results.push(result),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+    results
+}
+
+impl Engine {
+    /// Копия движка, подписывающая все сообщения и попытки названием
+    /// аккаунта, со своим span `attempt`.
+    pub fn for_account(&self, label: &str) -> Engine {
+        let mut engine = self.clone().with_account(label);
+        engine.span = attempt_span(Some(label));
+        engine.events = Arc::new(AccountSink {
+            label: label.to_string(),
+            inner: self.events.clone(),
+        });
+        engine
+    }
+
+    /// Подает заявки для всех аккаунтов к одной полуночи. Итоги - в порядке
+    /// `accounts`, `clock` - как в [`submit`](Engine::submit).
+    pub async fn submit_all(
+        &self,
+        clock: &Arc<dyn Clock>,
+        accounts: &[Account],
+    ) -> Vec<AccountOutcome> {
+        let span = info_span!("queue", accounts = accounts.len(), ping_ms = Empty);
+        self.submit_queue(clock, accounts).instrument(span).await
+    }
+
+    async fn submit_queue(
+        &self,
+        clock: &Arc<dyn Clock>,
+        accounts: &[Account],
+    ) -> Vec<AccountOutcome> {
+        self.log(format!("Очередь из {} аккаунтов", accounts.len()));
+        // Стратегия каждого аккаунта подбирается по его собственной истории
+        let queue: Vec<(Engine, Account)> = accounts
+            .iter()
+            .map(|account| (self.for_account(&account.label).learned(), account.clone()))
+            .collect();
+
+        let checks = for_each_account(&queue, |engine, account| {
+            let clock = clock.clone();
+            async move {
+                let check = engine
+                    .check_eligible(clock.as_ref(), &account.cookie_value, &account.device_id)
+                    .await;
+                if let Err(outcome) = &check {
+                    engine.record(AttemptRecord::new(outcome));
+                }
+                check
+            }
+        })
+        .await;
+        let mut outcomes: Vec<Option<ApplyOutcome>> =
+            checks.into_iter().map(|check| check.err()).collect();
+        let eligible: Vec<(Engine, Account)> = queue
+            .iter()
+            .zip(&outcomes)
+            .filter(|(_, outcome)| outcome.is_none())
+            .map(|(entry, _)| entry.clone())
+            .collect();
+
+        if !eligible.is_empty() {
+            self.log(format!(
+                "Заявку подают {} из {} аккаунтов",
+                eligible.len(),
+                accounts.len()
+            ));
+            let results = self.submit_eligible(&eligible).await;
+            let mut results = results.into_iter();
+            for outcome in outcomes.iter_mut().filter(|outcome| outcome.is_none()) {
+                *outcome = results.next();
+            }
+        }
+
+        let results: Vec<AccountOutcome> = accounts
+            .iter()
+            .zip(outcomes)
+            .map(|(account, outcome)| AccountOutcome {
+                label: account.label.clone(),
+                outcome: outcome.unwrap_or(ApplyOutcome::TimeSyncFailed),
+            })
+            .collect();
+        self.log("Итоги по аккаунтам:");
+        for result in &results {
+            self.log(format!("  {}: {}", result.label, result.outcome));
+        }
+        results
+    }
+
+    /// Общая синхронизация и замер задержки, затем заявки аккаунтов
+    /// параллельно, каждая к своему времени отправки.
+    async fn submit_eligible(&self, eligible: &[(Engine, Account)]) -> Vec<ApplyOutcome> {
+        let Some(clock) = self.sync_clock().await else {
+            self.error("Ошибка получения начального времени");
+            for (engine, _) in eligible {
+                engine.record(AttemptRecord::new(&ApplyOutcome::TimeSyncFailed));
+            }
+            return vec![ApplyOutcome::TimeSyncFailed; eligible.len()];
+        };
+        let synced = SharedClock::new(clock);
+        let resync = self.spawn_resync(synced.clone());
+        let clock: Arc<dyn Clock> = Arc::new(synced);
+        let latency = self.wait_until_ping_time(clock.as_ref()).await;
+        Span::current().record("ping_ms", latency);
+
+        let warm_ups = for_each_account(eligible, |engine, account| {
+            let clock = clock.clone();
+            async move {
+                let target_time = engine.target_time(clock.as_ref(), latency);
+                let warm_addr = engine
+                    .warm_up_connection(
+                        clock.as_ref(),
+                        target_time,
+                        &account.cookie_value,
+                        &account.device_id,
+                    )
+                    .await;
+                (target_time, warm_addr)
+            }
+        })
+        .await;
+        resync.abort();
+
+        let queue: Vec<(Engine, Prepared)> = eligible
+            .iter()
+            .zip(warm_ups)
+            .map(|((engine, account), (target_time, warm_addr))| {
+                let prepared = Prepared {
+                    account: account.clone(),
+                    target_time,
+                    warm_addr,
+                };
+                (engine.clone(), prepared)
+            })
+            .collect();
+        for_each_account(&queue, |engine, prepared| {
+            let clock = clock.clone();
+            async move {
+                engine
+                    .apply(
+                        &clock,
+                        prepared.target_time,
+                        latency,
+                        &prepared.account.cookie_value,
+                        &prepared.account.device_id,
+                        prepared.warm_addr,
+                    )
+                    .await
+            }
+        })
+        .await
+    }
+}
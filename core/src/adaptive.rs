@@ -0,0 +1,174 @@
+// adaptive.rs
+// Стратегия, которая сдвигает момент отправки базовой стратегии по итогам
+// прошлых ночей из истории: раньше, если запросы опаздывали, позже, если
+// приходили до сброса лимита.
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta};
+use chrono_tz::{Asia::Shanghai, Tz};
+
+use crate::history::AttemptRecord;
+use crate::network::{SendStrategy, nearest_midnight};
+
+// Сколько последних попыток усреднять
+const HISTORY_WINDOW: usize = 10;
+// Минимальный шаг поправки, если время прихода запроса неизвестно
+const STEP_MS: i64 = 100;
+// Запас, с которым запрос должен прийти после сброса лимита
+const MARGIN_MS: i64 = 30;
+
+/// Чем закончилась прошлая попытка с точки зрения времени отправки.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Запрос пришел до сброса лимита и не прошел: отправлять позже.
+    Early { reset_offset_ms: i64 },
+    /// Запрос пришел после сброса (или время неизвестно), а лимит уже занят
+    /// или выдана блокировка: отправлять раньше.
+    Late { reset_offset_ms: Option<i64> },
+    /// Заявка одобрена или принята: время было удачным.
+    Success,
+}
+
+impl Timing {
+    /// Оценка попытки или `None`, если она ничего не говорит о времени
+    /// (заявка не отправлялась, ошибка запроса).
+    pub fn of(record: &AttemptRecord) -> Option<Timing> {
+        if record.success {
+            return Some(Timing::Success);
+        }
+        let request = record.decisive_request()?;
+        if request.code != Some(0) {
+            return None;
+        }
+        match (request.apply_result, request.reset_offset_ms) {
+            (Some(3) | Some(4), Some(reset_offset_ms)) if reset_offset_ms < 0 => {
+                Some(Timing::Early { reset_offset_ms })
+            }
+            (Some(3) | Some(4), reset_offset_ms) => Some(Timing::Late { reset_offset_ms }),
+            _ => None,
+        }
+    }
+}
+
+/// Базовая стратегия плюс поправка, выученная по истории.
+pub struct AdaptiveStrategy {
+    base: Arc<dyn SendStrategy>,
+    shift: TimeDelta,
+    explanation: String,
+}
+
+impl AdaptiveStrategy {
+    /// Поправка по последним попыткам `history` (по порядку записи), не больше
+    /// `max_shift` в любую сторону. Для каждой попытки находим поправку, при
+    /// которой ее запрос пришел бы сразу после сброса лимита (с учетом того,
+    /// насколько ее момент отправки отличался от базовой стратегии), и берем
+    /// среднее.
+    pub fn learn(
+        base: Arc<dyn SendStrategy>,
+        history: &[AttemptRecord],
+        max_shift: Duration,
+    ) -> Self {
+        let max_ms = max_shift.as_millis() as i64;
+        let nights: Vec<(Timing, i64)> = history
+            .iter()
+            .filter_map(|record| {
+                let timing = Timing::of(record)?;
+                let used_ms = used_shift_ms(base.as_ref(), record)?;
+                Some((timing, used_ms + correction_ms(timing)))
+            })
+            .collect();
+        let recent = &nights[nights.len().saturating_sub(HISTORY_WINDOW)..];
+
+        let shift_ms = if recent.is_empty() {
+            0
+        } else {
+            let sum: i64 = recent.iter().map(|(_, ideal_ms)| ideal_ms).sum();
+            (sum / recent.len() as i64).clamp(-max_ms, max_ms)
+        };
+
+        let explanation = match recent.last() {
+            None => "истории нет".to_string(),
+            Some((last, _)) => {
+                let count = |f: fn(&Timing) -> bool| recent.iter().filter(|(t, _)| f(t)).count();
+                format!(
+                    "по {} попыткам: рано {}, поздно {}, успешно {}; последняя: {}{}",
+                    recent.len(),
+                    count(|t| matches!(t, Timing::Early { .. })),
+                    count(|t| matches!(t, Timing::Late { .. })),
+                    count(|t| matches!(t, Timing::Success)),
+                    describe_timing(last),
+                    if shift_ms.abs() == max_ms && max_ms > 0 {
+                        format!(", поправка ограничена {} мс", max_ms)
+                    } else {
+                        String::new()
+                    }
+                )
+            }
+        };
+        AdaptiveStrategy {
+            base,
+            shift: TimeDelta::milliseconds(shift_ms),
+            explanation,
+        }
+    }
+
+    /// Поправка к моменту отправки базовой стратегии (> 0 - позже).
+    pub fn shift(&self) -> TimeDelta {
+        self.shift
+    }
+}
+
+/// Насколько момент отправки решающего запроса попытки отличался от базовой
+/// стратегии, мс. В серии запрос сдвинут от ее центра на свой интервал, и
+/// поправка по времени его прихода относится именно к нему.
+fn used_shift_ms(base: &dyn SendStrategy, record: &AttemptRecord) -> Option<i64> {
+    let target_time = record.target_time?.with_timezone(&Shanghai);
+    let scheduled = record
+        .decisive_request()
+        .map_or(target_time, |request| request.scheduled.with_timezone(&Shanghai));
+    let latency_ms = record.latency_ms?;
+    let midnight = nearest_midnight(target_time);
+    Some((scheduled - base.target_time(midnight, latency_ms)).num_milliseconds())
+}
+
+/// На сколько сдвинуть отправку после такой попытки, мс (> 0 - позже).
+fn correction_ms(timing: Timing) -> i64 {
+    match timing {
+        Timing::Early { reset_offset_ms } => (-reset_offset_ms + MARGIN_MS).max(STEP_MS),
+        Timing::Late {
+            reset_offset_ms: Some(reset_offset_ms),
+        } if reset_offset_ms > 0 => -(reset_offset_ms - MARGIN_MS).max(STEP_MS),
+        Timing::Late { .. } => -STEP_MS,
+        Timing::Success => 0,
+    }
+}
+
+fn describe_timing(timing: &Timing) -> String {
+    match timing {
+        Timing::Early { reset_offset_ms } => {
+            format!("рано, за {} мс до сброса", -reset_offset_ms)
+        }
+        Timing::Late {
+            reset_offset_ms: Some(reset_offset_ms),
+        } => format!("поздно, {:+} мс от сброса", reset_offset_ms),
+        Timing::Late {
+            reset_offset_ms: None,
+        } => "поздно".to_string(),
+        Timing::Success => "успешно".to_string(),
+    }
+}
+
+impl SendStrategy for AdaptiveStrategy {
+    fn target_time(&self, midnight: DateTime<Tz>, latency_ms: f64) -> DateTime<Tz> {
+        self.base.target_time(midnight, latency_ms) + self.shift
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{}, поправка по истории {:+} мс ({})",
+            self.base.describe(),
+            self.shift.num_milliseconds(),
+            self.explanation
+        )
+    }
+}
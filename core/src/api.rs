@@ -0,0 +1,200 @@
+// api.rs
+// Типизированный клиент эндпоинтов Mi Community, связанных с загрузчиком.
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url, header::DATE};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+
+pub const API_BASE_URL: &str = "https://sgp-api.buy.mi.com/bbs/api/global";
+pub const STATE_PATH: &str = "/user/bl-switch/state";
+pub const APPLY_PATH: &str = "/apply/bl-auth";
+
+/// Код API: заявка отклонена, ошибка запроса.
+pub const CODE_REQUEST_REJECTED: i64 = 100001;
+
+/// Код API: токен устарел.
+pub const CODE_TOKEN_EXPIRED: i64 = 100004;
+
+/// Ошибка запроса к API.
+#[derive(Debug)]
+pub enum ApiError {
+    /// Запрос не дошел или ответ не прочитан (DNS, TCP, TLS, таймаут).
+    Transport(reqwest::Error),
+    /// Сервер ответил статусом, отличным от 2xx.
+    HttpStatus(StatusCode),
+    /// Тело ответа не соответствует ожидаемой структуре.
+    Json(serde_json::Error),
+    /// Код 0, но без поля `data`.
+    MissingData,
+    /// API вернуло ненулевой код.
+    Api { code: i64, message: Option<String> },
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Transport(e) => write!(f, "ошибка соединения: {}", e),
+            ApiError::HttpStatus(status) => write!(f, "HTTP {}", status),
+            ApiError::Json(e) => write!(f, "неожиданный формат ответа: {}", e),
+            ApiError::MissingData => write!(f, "в ответе нет поля data"),
+            ApiError::Api {
+                code,
+                message: Some(message),
+            } => write!(f, "code {} ({})", code, message),
+            ApiError::Api {
+                code,
+                message: None,
+            } => write!(f, "code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        ApiError::Transport(e)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    code: i64,
+    #[serde(default)]
+    msg: Option<String>,
+    #[serde(default)]
+    data: Value,
+}
+
+/// Ответ `user/bl-switch/state`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlSwitchState {
+    pub is_pass: i64,
+    #[serde(default)]
+    pub button_state: Option<i64>,
+    #[serde(default)]
+    pub deadline_format: Option<String>,
+}
+
+/// Ответ `apply/bl-auth`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApplyResult {
+    pub apply_result: i64,
+    #[serde(default)]
+    pub deadline_format: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct MiCommunityClient {
+    session: Client,
+    base_url: String,
+}
+
+impl MiCommunityClient {
+    pub fn new(session: Client) -> Self {
+        MiCommunityClient::with_base_url(session, API_BASE_URL)
+    }
+
+    pub fn with_base_url(session: Client, base_url: &str) -> Self {
+        MiCommunityClient {
+            session,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn session(&self) -> &Client {
+        &self.session
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Хост, порт и признак HTTPS базового URL (для замера задержки).
+    pub fn endpoint(&self) -> Option<(String, u16, bool)> {
+        let url = Url::parse(&self.base_url).ok()?;
+        let host = url.host_str()?.to_string();
+        let port = url.port_or_known_default()?;
+        Some((host, port, url.scheme() == "https"))
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub fn cookie(cookie_value: &str, device_id: &str) -> String {
+        format!("new_bbs_serviceToken={cookie_value};versionCode=500411;versionName=5.4.11;deviceId={device_id};")
+    }
+
+    /// Запрос статуса разблокировки (еще не отправленный).
+    pub fn state_request(&self, cookie_value: &str, device_id: &str) -> RequestBuilder {
+        self.session
+            .get(self.url(STATE_PATH))
+            .header("Cookie", MiCommunityClient::cookie(cookie_value, device_id))
+            .header("Content-Type", "application/json; charset=utf-8")
+    }
+
+    /// Запрос подачи заявки (еще не отправленный).
+    pub fn apply_request(&self, cookie_value: &str, device_id: &str) -> RequestBuilder {
+        self.session
+            .post(self.url(APPLY_PATH))
+            .header("Cookie", MiCommunityClient::cookie(cookie_value, device_id))
+            .header("User-Agent", "okhttp/4.9.3")
+            .header("Accept-Encoding", "gzip, deflate, br")
+            .header("Connection", "keep-alive")
+    }
+
+    /// Отправляет запрос и проверяет HTTP-статус.
+    pub async fn send(request: RequestBuilder) -> Result<Response, ApiError> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(ApiError::HttpStatus(response.status()));
+        }
+        Ok(response)
+    }
+
+    /// Время сервера из заголовка `Date` (с точностью до секунды).
+    pub fn server_date(response: &Response) -> Option<DateTime<Utc>> {
+        let date = response.headers().get(DATE)?.to_str().ok()?;
+        DateTime::parse_from_rfc2822(date)
+            .ok()
+            .map(|date| date.with_timezone(&Utc))
+    }
+
+    /// Разбирает конверт `{code, msg, data}`: ненулевой code - ошибка API.
+    pub async fn parse<T: DeserializeOwned>(response: Response) -> Result<T, ApiError> {
+        let body = response.bytes().await?;
+        // data разбираем только при code 0: у ошибок он бывает любой формы
+        let envelope: Envelope = serde_json::from_slice(&body).map_err(ApiError::Json)?;
+        if envelope.code != 0 {
+            return Err(ApiError::Api {
+                code: envelope.code,
+                message: envelope.msg,
+            });
+        }
+        if envelope.data.is_null() {
+            return Err(ApiError::MissingData);
+        }
+        serde_json::from_value(envelope.data).map_err(ApiError::Json)
+    }
+
+    pub async fn bl_switch_state(
+        &self,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> Result<BlSwitchState, ApiError> {
+        let response = MiCommunityClient::send(self.state_request(cookie_value, device_id)).await?;
+        MiCommunityClient::parse(response).await
+    }
+
+    pub async fn apply_bl_auth(
+        &self,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> Result<ApplyResult, ApiError> {
+        let response = MiCommunityClient::send(self.apply_request(cookie_value, device_id)).await?;
+        MiCommunityClient::parse(response).await
+    }
+}
@@ -0,0 +1,269 @@
+// burst.rs
+// Серия заявок вокруг целевого времени, чтобы одна неточная оценка
+// задержки не стоила целого дня.
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta};
+use chrono_tz::{Asia::Shanghai, Tz};
+use tokio::{sync::watch, task::JoinSet};
+use tracing::{Instrument, Span};
+
+use crate::api::{
+    ApiError, ApplyResult, CODE_REQUEST_REJECTED, CODE_TOKEN_EXPIRED, MiCommunityClient,
+};
+use crate::clock::Clock;
+use crate::engine::Engine;
+use crate::network::nearest_midnight;
+
+// Сколько ждать ответа на заявку. Столько же после окончательного ответа
+// ждем запросы серии, которые уже отправлены
+const APPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Один запрос серии.
+#[derive(Debug, Clone)]
+pub struct BurstAttempt {
+    pub index: usize,
+    pub scheduled: DateTime<Tz>,
+    pub sent: DateTime<Tz>,
+    pub received: Option<DateTime<Tz>>,
+    /// Время сервера из заголовка `Date` ответа (точность - секунда).
+    pub server_date: Option<DateTime<Tz>>,
+    pub http_status: Option<u16>,
+    pub code: Option<i64>,
+    pub apply_result: Option<i64>,
+    pub deadline_format: Option<String>,
+    pub error: Option<String>,
+}
+
+impl BurstAttempt {
+    /// Одобрение, исчерпанный лимит или блокировка после сброса лимита,
+    /// отклоненная заявка или устаревший токен: продолжать серию незачем.
+    /// Отказ, пришедший до сброса, серию не останавливает - следующие запросы
+    /// могут успеть после него.
+    pub fn is_final(&self) -> bool {
+        match self.code {
+            Some(0) => match self.apply_result {
+                Some(1) => true,
+                Some(3) | Some(4) => self.reset_offset() >= Some(TimeDelta::zero()),
+                _ => false,
+            },
+            Some(CODE_REQUEST_REJECTED) | Some(CODE_TOKEN_EXPIRED) => true,
+            _ => false,
+        }
+    }
+
+    /// Исчерпанный лимит или блокировка, когда бы запрос ни пришел.
+    fn is_refusal(&self) -> bool {
+        self.code == Some(0) && matches!(self.apply_result, Some(3) | Some(4))
+    }
+
+    /// Когда запрос дошел до сервера: середина между отправкой и ответом по
+    /// синхронизированным часам. Если она не попадает в секунду из заголовка
+    /// `Date`, берется ближайшая граница этой секунды.
+    pub fn server_arrival(&self) -> Option<DateTime<Tz>> {
+        let received = self.received?;
+        let estimate = self.sent + (received - self.sent) / 2;
+        let Some(date) = self.server_date else {
+            return Some(estimate);
+        };
+        Some(estimate.clamp(date, date + TimeDelta::milliseconds(999)))
+    }
+
+    /// На сколько запрос дошел до сервера позже (> 0) или раньше (< 0)
+    /// ближайшего сброса лимита в полночь по Пекину.
+    pub fn reset_offset(&self) -> Option<TimeDelta> {
+        let arrival = self.server_arrival()?;
+        Some(arrival - nearest_midnight(arrival))
+    }
+}
+
+/// Смещения запросов относительно целевого времени, симметрично вокруг нуля.
+pub fn burst_offsets(count: usize, interval: Duration) -> Vec<TimeDelta> {
+    let interval = TimeDelta::from_std(interval).unwrap_or(TimeDelta::zero());
+    let center = (count.max(1) - 1) as f64 / 2f64;
+    (0..count.max(1))
+        .map(|i| {
+            let micros = interval.num_microseconds().unwrap_or(0) as f64 * (i as f64 - center);
+            TimeDelta::microseconds(micros.round() as i64)
+        })
+        .collect()
+}
+
+/// Запрос, по которому судим об итоге серии: одобрение важнее всего, затем
+/// последний окончательный ответ, затем последний отказ
+/// до сброса, затем первый ответ вообще.
+pub fn decisive_attempt(attempts: &[BurstAttempt]) -> Option<&BurstAttempt> {
+    attempts
+        .iter()
+        .find(|a| a.code == Some(0) && a.apply_result == Some(1))
+        .or_else(|| attempts.iter().rev().find(|a| a.is_final()))
+        .or_else(|| attempts.iter().rev().find(|a| a.is_refusal()))
+        .or_else(|| attempts.iter().find(|a| a.code.is_some()))
+}
+
+impl Engine {
+    /// Отправляет серию заявок вокруг `target_time` и возвращает все попытки
+    /// по порядку. После окончательного ответа неотправленные запросы
+    /// отменяются, а ответы на уже отправленные дожидаются.
+    pub async fn send_burst(
+        &self,
+        clock: &Arc<dyn Clock>,
+        target_time: DateTime<Tz>,
+        cookie_value: &str,
+        device_id: &str,
+        warm_addr: Option<SocketAddr>,
+    ) -> Vec<BurstAttempt> {
+        let offsets = burst_offsets(self.config.burst.count, self.config.burst.interval);
+        let total = offsets.len();
+        if total > 1 {
+            self.log(format!(
+                "Серия из {} запросов с интервалом {} мс вокруг {}",
+                total,
+                self.config.burst.interval.as_millis(),
+                target_time
+            ));
+        }
+
+        let (stop, stopped) = watch::channel(false);
+        let mut tasks = JoinSet::new();
+        for (index, offset) in offsets.into_iter().enumerate() {
+            let engine = self.clone();
+            let span = Span::current();
+            let clock = clock.clone();
+            let mut stopped = stopped.clone();
+            let cookie_value = cookie_value.to_string();
+            let device_id = device_id.to_string();
+            tasks.spawn(async move {
+                let scheduled = target_time + offset;
+                tokio::select! {
+                    biased;
+                    _ = stopped.wait_for(|stopped| *stopped) => return None,
+                    _ = engine.wait_until(clock.as_ref(), scheduled) => {}
+                }
+                Some(
+                    engine
+                        .send_apply(
+                            clock.as_ref(),
+                            index,
+                            total,
+                            scheduled,
+                            &cookie_value,
+                            &device_id,
+                            warm_addr,
+                        )
+                        .instrument(span)
+                        .await,
+                )
+            });
+        }
+
+        let mut attempts = vec![];
+        while let Some(result) = tasks.join_next().await {
+            if let Ok(Some(attempt)) = result {
+                if attempt.is_final() && !stop.send_replace(true) && total > 1 {
+                    // Ждущие своего времени запросы больше не нужны, а ответ
+                    // на отправленный может оказаться одобрением
+                    self.log(format!(
+                        "Окончательный ответ на запрос {}/{}, неотправленные запросы отменены",
+                        attempt.index + 1,
+                        total
+                    ));
+                }
+                attempts.push(attempt);
+            }
+        }
+        attempts.sort_by_key(|a| a.index);
+        attempts
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send_apply(
+        &self,
+        clock: &dyn Clock,
+        index: usize,
+        total: usize,
+        scheduled: DateTime<Tz>,
+        cookie_value: &str,
+        device_id: &str,
+        warm_addr: Option<SocketAddr>,
+    ) -> BurstAttempt {
+        let request = self
+            .client
+            .apply_request(cookie_value, device_id)
+            .timeout(APPLY_TIMEOUT);
+        let sent = clock.now();
+        self.log(format!(
+            "Отправка запроса {}/{} в {} (Пекинское время)",
+            index + 1,
+            total,
+            sent
+        ));
+        let mut attempt = BurstAttempt {
+            index,
+            scheduled,
+            sent,
+            received: None,
+            server_date: None,
+            http_status: None,
+            code: None,
+            apply_result: None,
+            deadline_format: None,
+            error: None,
+        };
+
+        let response = match MiCommunityClient::send(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                self.warn(format!(
+                    "Ошибка отправки запроса {}/{}: {}",
+                    index + 1,
+                    total,
+                    e
+                ));
+                if let ApiError::HttpStatus(status) = &e {
+                    attempt.http_status = Some(status.as_u16());
+                }
+                attempt.error = Some(e.to_string());
+                return attempt;
+            }
+        };
+        let received = clock.now();
+        attempt.received = Some(received);
+        attempt.http_status = Some(response.status().as_u16());
+        attempt.server_date =
+            MiCommunityClient::server_date(&response).map(|date| date.with_timezone(&Shanghai));
+        // Прогретое соединение одно, по нему может уйти только первый запрос
+        if index == 0 {
+            self.log_connection_reuse(warm_addr, &response);
+        }
+
+        match MiCommunityClient::parse::<ApplyResult>(response).await {
+            Ok(result) => {
+                attempt.code = Some(0);
+                attempt.apply_result = Some(result.apply_result);
+                attempt.deadline_format = result.deadline_format;
+            }
+            Err(ApiError::Api { code, .. }) => {
+                attempt.code = Some(code);
+            }
+            Err(e) => {
+                self.warn(format!(
+                    "Ошибка разбора ответа {}/{}: {}",
+                    index + 1,
+                    total,
+                    e
+                ));
+                attempt.error = Some(e.to_string());
+            }
+        }
+        self.log(format!(
+            "Ответ {}/{} получен в {} (Пекинское время): code {}, apply_result {}",
+            index + 1,
+            total,
+            received,
+            attempt.code.map_or("-".to_string(), |c| c.to_string()),
+            attempt.apply_result.map_or("-".to_string(), |r| r.to_string())
+        ));
+        attempt
+    }
+}
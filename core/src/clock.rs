@@ -0,0 +1,148 @@
+// clock.rs
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use chrono_tz::{Asia::Shanghai, Tz};
+
+/// Источник пекинского времени для планирования. Ожидание по нему идет через
+/// `tokio::time::sleep`, поэтому в тестах его можно заменить на [`FakeClock`].
+pub trait Clock: Send + Sync {
+    /// Текущее пекинское время.
+    fn now(&self) -> DateTime<Tz>;
+
+    /// Часы NTP, если их можно пересинхронизировать во время ожидания.
+    fn synced(&self) -> Option<&SharedClock> {
+        None
+    }
+}
+
+/// Системные часы без синхронизации, для долгого ожидания, где секунды не важны.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&Shanghai)
+    }
+}
+
+/// Часы, синхронизированные по NTP.
+///
+/// Хранит смещение системных часов относительно сервера с точностью до
+/// микросекунд, а текущее время отсчитывает от монотонного [`Instant`], поэтому
+/// переводы системных часов после синхронизации на него не влияют.
+#[derive(Debug, Clone, Copy)]
+pub struct SyncedClock {
+    synced_utc: DateTime<Utc>,
+    synced_instant: Instant,
+    offset: TimeDelta,
+    round_trip: Duration,
+}
+
+impl SyncedClock {
+    /// `offset` - на сколько системные часы отстают от сервера,
+    /// `round_trip` - задержка запроса к серверу туда и обратно.
+    pub fn new(offset: TimeDelta, round_trip: Duration) -> Self {
+        let synced_instant = Instant::now();
+        let synced_utc = Utc::now() + offset;
+        SyncedClock {
+            synced_utc,
+            synced_instant,
+            offset,
+            round_trip,
+        }
+    }
+
+    /// Текущее пекинское время.
+    pub fn now(&self) -> DateTime<Tz> {
+        let elapsed = TimeDelta::from_std(self.synced_instant.elapsed()).unwrap_or(TimeDelta::zero());
+        (self.synced_utc + elapsed).with_timezone(&Shanghai)
+    }
+
+    /// Пекинское время в момент синхронизации.
+    pub fn synced_at(&self) -> DateTime<Tz> {
+        self.synced_utc.with_timezone(&Shanghai)
+    }
+
+    pub fn offset(&self) -> TimeDelta {
+        self.offset
+    }
+
+    pub fn offset_ms(&self) -> f64 {
+        self.offset.num_microseconds().unwrap_or(0) as f64 / 1000f64
+    }
+
+    pub fn round_trip(&self) -> Duration {
+        self.round_trip
+    }
+
+    pub fn round_trip_ms(&self) -> f64 {
+        self.round_trip.as_secs_f64() * 1000f64
+    }
+}
+
+/// Общие часы ([`SyncedClock`]), которые можно обновить во время ожидания
+/// (фоновая пересинхронизация), не прерывая тех, кто по ним ждет.
+#[derive(Debug, Clone)]
+pub struct SharedClock(Arc<RwLock<SyncedClock>>);
+
+impl SharedClock {
+    pub fn new(clock: SyncedClock) -> Self {
+        SharedClock(Arc::new(RwLock::new(clock)))
+    }
+
+    pub fn get(&self) -> SyncedClock {
+        *self.0.read().unwrap()
+    }
+
+    pub fn set(&self, clock: SyncedClock) {
+        *self.0.write().unwrap() = clock;
+    }
+
+    pub fn now(&self) -> DateTime<Tz> {
+        self.get().now()
+    }
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> DateTime<Tz> {
+        SharedClock::now(self)
+    }
+
+    fn synced(&self) -> Option<&SharedClock> {
+        Some(self)
+    }
+}
+
+/// Управляемые часы для тестов: идут от заданного времени по
+/// `tokio::time::Instant`, поэтому с `tokio::time::pause` время сдвигается
+/// только вместе со сном runtime или через [`FakeClock::advance`].
+#[derive(Debug)]
+pub struct FakeClock(Mutex<(DateTime<Tz>, tokio::time::Instant)>);
+
+impl FakeClock {
+    pub fn new(start: DateTime<Tz>) -> Self {
+        FakeClock(Mutex::new((start, tokio::time::Instant::now())))
+    }
+
+    /// Переводит часы на `time` (как перевод системных часов).
+    pub fn set(&self, time: DateTime<Tz>) {
+        *self.0.lock().unwrap() = (time, tokio::time::Instant::now());
+    }
+
+    /// Сдвигает часы на `delta`, не трогая время runtime.
+    pub fn advance(&self, delta: TimeDelta) {
+        let now = Clock::now(self);
+        self.set(now + delta);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Tz> {
+        let (start, instant) = *self.0.lock().unwrap();
+        start + TimeDelta::from_std(instant.elapsed()).unwrap_or(TimeDelta::zero())
+    }
+}
@@ -0,0 +1,217 @@
+// config.rs
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
+
+use chrono::NaiveTime;
+
+use crate::adaptive::AdaptiveStrategy;
+use crate::api::API_BASE_URL;
+use crate::history::AttemptRecord;
+use crate::network::{
+    FixedOffsetStrategy, FixedTimeStrategy, FormulaStrategy, HalfRttStrategy, SendStrategy,
+};
+use crate::ntp::DEFAULT_NTP_SERVERS;
+
+/// Настройки движка.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub api: ApiConfig,
+    pub ntp: NtpConfig,
+    pub burst: BurstConfig,
+    pub schedule: ScheduleConfig,
+    pub retry: RetryConfig,
+}
+
+/// Адрес API.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    /// Базовый URL, к которому добавляются пути эндпоинтов.
+    pub base_url: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            base_url: API_BASE_URL.to_string(),
+        }
+    }
+}
+
+/// Настройки синхронизации времени.
+#[derive(Debug, Clone)]
+pub struct NtpConfig {
+    /// Опрашиваемые серверы (`хост:порт`).
+    pub servers: Vec<String>,
+    /// Таймаут ответа одного сервера.
+    pub timeout: Duration,
+    /// Минимальное число ответивших серверов.
+    pub min_servers: usize,
+    /// Максимальный разброс смещений среди согласных серверов.
+    pub max_disagreement: Duration,
+    /// Как часто пересинхронизироваться во время ожидания.
+    pub resync_interval: Duration,
+}
+
+impl Default for NtpConfig {
+    fn default() -> Self {
+        NtpConfig {
+            servers: DEFAULT_NTP_SERVERS.iter().map(|s| s.to_string()).collect(),
+            timeout: Duration::from_secs(2),
+            min_servers: 3,
+            max_disagreement: Duration::from_millis(100),
+            resync_interval: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// Серия заявок вокруг целевого времени.
+#[derive(Debug, Clone)]
+pub struct BurstConfig {
+    /// Число запросов (1 - одиночный запрос ровно в целевое время).
+    pub count: usize,
+    /// Интервал между запросами.
+    pub interval: Duration,
+}
+
+impl Default for BurstConfig {
+    fn default() -> Self {
+        BurstConfig {
+            count: 1,
+            interval: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Когда измерять задержку и как выбирать момент отправки.
+#[derive(Debug, Clone)]
+pub struct ScheduleConfig {
+    pub strategy: StrategyConfig,
+    /// За сколько до времени отправки по стратегии измерять задержку.
+    pub measure_before: Duration,
+    /// apply_result 4 считается принятой заявкой, если сервер получил запрос
+    /// не дальше столька от сброса лимита, до или после него (и статус не
+    /// показал блокировку).
+    pub accept_window: Duration,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            strategy: StrategyConfig::default(),
+            measure_before: Duration::from_secs(12),
+            accept_window: Duration::from_millis(3350),
+        }
+    }
+}
+
+/// Ночные повторы до одобрения заявки.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Сколько ночей подряд пробовать (0 - без ограничения).
+    pub max_days: u32,
+    /// За сколько до полуночи просыпаться: проверка токена и синхронизация.
+    pub wake_before: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_days: 30,
+            wake_before: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Встроенные стратегии отправки. Строковый вид:
+/// `formula`, `fixed-offset:<мс>`, `half-rtt[:<поправка мс>]`, `at:<ЧЧ:ММ:СС[.ммм]>`,
+/// `adaptive[:<макс. поправка мс>]`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum StrategyConfig {
+    #[default]
+    Formula,
+    FixedOffset(Duration),
+    HalfRtt { bias_ms: i64 },
+    At(NaiveTime),
+    /// Формула с поправкой по истории попыток, не больше `max_shift`.
+    Adaptive { max_shift: Duration },
+}
+
+// Ограничение поправки адаптивной стратегии по умолчанию
+const ADAPTIVE_MAX_SHIFT: Duration = Duration::from_millis(1500);
+
+impl StrategyConfig {
+    pub fn build(&self) -> Arc<dyn SendStrategy> {
+        self.build_with_history(&[])
+    }
+
+    /// Стратегия, которая учится на `history` (важно только для `adaptive`).
+    pub fn build_with_history(&self, history: &[AttemptRecord]) -> Arc<dyn SendStrategy> {
+        match self {
+            StrategyConfig::Formula => Arc::new(FormulaStrategy),
+            StrategyConfig::FixedOffset(before_midnight) => Arc::new(FixedOffsetStrategy {
+                before_midnight: *before_midnight,
+            }),
+            StrategyConfig::HalfRtt { bias_ms } => Arc::new(HalfRttStrategy { bias_ms: *bias_ms }),
+            StrategyConfig::At(time) => Arc::new(FixedTimeStrategy { time: *time }),
+            StrategyConfig::Adaptive { max_shift } => Arc::new(AdaptiveStrategy::learn(
+                Arc::new(FormulaStrategy),
+                history,
+                *max_shift,
+            )),
+        }
+    }
+}
+
+impl FromStr for StrategyConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        match (name, value) {
+            ("formula", None) => Ok(StrategyConfig::Formula),
+            ("fixed-offset", Some(ms)) => ms
+                .parse::<u64>()
+                .map(|ms| StrategyConfig::FixedOffset(Duration::from_millis(ms)))
+                .map_err(|e| format!("fixed-offset: {}", e)),
+            ("half-rtt", None) => Ok(StrategyConfig::HalfRtt { bias_ms: 0 }),
+            ("half-rtt", Some(ms)) => ms
+                .parse::<i64>()
+                .map(|bias_ms| StrategyConfig::HalfRtt { bias_ms })
+                .map_err(|e| format!("half-rtt: {}", e)),
+            ("at", Some(time)) => NaiveTime::parse_from_str(time, "%H:%M:%S%.f")
+                .map(StrategyConfig::At)
+                .map_err(|e| format!("at: {}", e)),
+            ("adaptive", None) => Ok(StrategyConfig::Adaptive {
+                max_shift: ADAPTIVE_MAX_SHIFT,
+            }),
+            ("adaptive", Some(ms)) => ms
+                .parse::<u64>()
+                .map(|ms| StrategyConfig::Adaptive {
+                    max_shift: Duration::from_millis(ms),
+                })
+                .map_err(|e| format!("adaptive: {}", e)),
+            _ => Err(format!(
+                "неизвестная стратегия '{}' (formula, fixed-offset:<мс>, half-rtt[:<мс>], at:<ЧЧ:ММ:СС.ммм>, adaptive[:<мс>])",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StrategyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StrategyConfig::Formula => write!(f, "formula"),
+            StrategyConfig::FixedOffset(before_midnight) => {
+                write!(f, "fixed-offset:{}", before_midnight.as_millis())
+            }
+            StrategyConfig::HalfRtt { bias_ms } => write!(f, "half-rtt:{}", bias_ms),
+            StrategyConfig::At(time) => write!(f, "at:{}", time.format("%H:%M:%S%.3f")),
+            StrategyConfig::Adaptive { max_shift } => {
+                write!(f, "adaptive:{}", max_shift.as_millis())
+            }
+        }
+    }
+}
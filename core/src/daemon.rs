@@ -0,0 +1,125 @@
+// daemon.rs
+// Ночные повторы: после каждого итога ждем следующей подходящей полуночи
+// (с учетом блокировки из deadline_format) и подаем заявку снова, пока она не
+// одобрена, токен не устарел или не вышел срок.
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta};
+use chrono_tz::Tz;
+
+use crate::clock::Clock;
+use crate::engine::Engine;
+use crate::network::next_midnight;
+use crate::outcome::{ApplyOutcome, UnlockStatus};
+
+// Пауза перед повтором, если ошибка случилась до отправки и полночь еще впереди
+const RETRY_PAUSE: Duration = Duration::from_secs(30);
+
+/// Итог, после которого можно повторить ту же полночь: заявка не отправлялась.
+fn is_transient(outcome: &ApplyOutcome) -> bool {
+    matches!(
+        outcome,
+        ApplyOutcome::TimeSyncFailed
+            | ApplyOutcome::RequestFailed(_)
+            | ApplyOutcome::NotEligible(
+                UnlockStatus::RequestFailed(_)
+                    | UnlockStatus::UnknownCode(_)
+                    | UnlockStatus::UnknownState { .. }
+            )
+    )
+}
+
+/// Полночь (Пекин), к которой стоит подать заявку снова после `outcome`, или
+/// `None`, если повторять незачем (одобрено или токен устарел).
+pub fn next_attempt_midnight(outcome: &ApplyOutcome, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let blocked_until = match outcome {
+        ApplyOutcome::Approved { .. }
+        | ApplyOutcome::Accepted
+        | ApplyOutcome::TokenExpired
+        | ApplyOutcome::NotEligible(UnlockStatus::Approved { .. } | UnlockStatus::TokenExpired) => {
+            return None;
+        }
+        // До снятия блокировки или лимита заявка заведомо отклоняется
+        ApplyOutcome::QuotaExhausted { .. }
+        | ApplyOutcome::TooLate { .. }
+        | ApplyOutcome::NotEligible(UnlockStatus::Blocked { .. }) => {
+            outcome.deadline().and_then(|deadline| deadline.midnight())
+        }
+        _ => None,
+    };
+    let midnight = next_midnight(now);
+    Some(match blocked_until {
+        Some(blocked_until) if blocked_until > midnight => blocked_until,
+        _ => midnight,
+    })
+}
+
+impl Engine {
+    /// Подает заявку каждую ночь, пока она не одобрена, токен не устарел или
+    /// не прошло `retry.max_days` ночей. Между попытками ждет по `clock`.
+    pub async fn run_daemon(
+        &self,
+        clock: &dyn Clock,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> ApplyOutcome {
+        let max_days = self.config.retry.max_days;
+        let wake_before =
+            TimeDelta::from_std(self.config.retry.wake_before).unwrap_or(TimeDelta::zero());
+        let last_midnight = (max_days > 0)
+            .then(|| next_midnight(clock.now()) + TimeDelta::days(max_days as i64 - 1));
+        let mut attempted: Option<DateTime<Tz>> = None;
+        let mut attempt = 1;
+        loop {
+            self.log(format!("Ночной повтор: попытка {}", attempt));
+            let outcome = self.submit(clock, cookie_value, device_id).await;
+            let now = clock.now();
+            let Some(mut midnight) = next_attempt_midnight(&outcome, now) else {
+                self.log(format!("Ночные повторы завершены: {}", outcome));
+                return outcome;
+            };
+            let transient = is_transient(&outcome);
+            // Не подаем дважды к одной полуночи, кроме ошибок до отправки
+            if !transient && (attempted == Some(midnight) || midnight - wake_before <= now) {
+                midnight += TimeDelta::days(1);
+            }
+            if let Some(last_midnight) = last_midnight
+                && midnight > last_midnight
+            {
+                self.log(format!(
+                    "Прошло {} дней, ночные повторы остановлены: {}",
+                    max_days, outcome
+                ));
+                return outcome;
+            }
+
+            let wake = midnight - wake_before;
+            self.log(format!("Итог: {}", outcome));
+            if let Some(deadline) = outcome.deadline()
+                && deadline.midnight() == Some(midnight)
+                && midnight > next_midnight(now)
+            {
+                self.log(format!(
+                    "Дни до {} пропускаются: заявка будет отклонена",
+                    deadline
+                ));
+            }
+            if wake > now {
+                self.log(format!(
+                    "Следующая попытка к полуночи {}, пробуждение в {} (Пекинское время)",
+                    midnight, wake
+                ));
+                self.wait_until(clock, wake).await;
+            } else {
+                self.log(format!(
+                    "Повтор к полуночи {} через {} с",
+                    midnight,
+                    RETRY_PAUSE.as_secs()
+                ));
+                tokio::time::sleep(RETRY_PAUSE).await;
+            }
+            attempted = Some(midnight);
+            attempt += 1;
+        }
+    }
+}
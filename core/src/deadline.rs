@@ -0,0 +1,90 @@
+// deadline.rs
+// deadline_format из ответов API: строка "Месяц/День" без года. Переводим ее в
+// дату по Пекину, чтобы планировщик не тратил ночи на заведомо отклоненные заявки.
+use std::fmt;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+
+/// Дата из `deadline_format` и исходная строка.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deadline {
+    /// Строка от сервера, например `10/25`.
+    pub text: String,
+    /// Дата по Пекину, если строку удалось разобрать.
+    pub date: Option<NaiveDate>,
+}
+
+// На сколько дней дата может оказаться в прошлом: запас на расхождение часов
+// с сервером
+const PAST_TOLERANCE_DAYS: i64 = 3;
+
+/// Ближайшая дата "Месяц/День", не раньше чем за несколько дней до `today`:
+/// год сервер не присылает, а сроки в ответах впереди. `01/02`, полученное
+/// 30 декабря, - это 2 января следующего года, а `12/31`, полученное
+/// 2 января, - только что прошедший день.
+pub fn parse_month_day(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let (month, day) = text.trim().split_once('/')?;
+    let month: u32 = month.trim().parse().ok()?;
+    let day: u32 = day.trim().parse().ok()?;
+    let earliest = today - TimeDelta::days(PAST_TOLERANCE_DAYS);
+    // 29 февраля может наступить только через несколько лет
+    (today.year() - 1..=today.year() + 4)
+        .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+        .find(|date| *date >= earliest)
+}
+
+impl Deadline {
+    /// Разбирает `text`, полученный в день `today` (по Пекину).
+    pub fn parse(text: &str, today: NaiveDate) -> Deadline {
+        Deadline {
+            text: text.to_string(),
+            date: parse_month_day(text, today),
+        }
+    }
+
+    /// `deadline_format` из ответа, если он есть.
+    pub fn from_api(text: Option<&str>, today: NaiveDate) -> Option<Deadline> {
+        text.filter(|text| !text.trim().is_empty())
+            .map(|text| Deadline::parse(text, today))
+    }
+
+    /// Начало даты, полночь по Пекину.
+    pub fn midnight(&self) -> Option<DateTime<Tz>> {
+        let date = self.date?;
+        Shanghai
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .single()
+    }
+
+    /// Сколько осталось до начала даты (отрицательное - уже наступила).
+    pub fn remaining(&self, now: DateTime<Tz>) -> Option<TimeDelta> {
+        self.midnight().map(|midnight| midnight - now)
+    }
+}
+
+impl fmt::Display for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.date {
+            Some(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// Оставшееся время для интерфейса: `2 д 03:12:45`.
+pub fn format_remaining(remaining: TimeDelta) -> String {
+    let total = remaining.num_seconds().max(0);
+    let (days, rest) = (total / 86_400, total % 86_400);
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    );
+    if days > 0 {
+        format!("{} д {}", days, time)
+    } else {
+        time
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use sha1::Digest;
+use sha1::Sha1;
+
+// Максимальная длина deviceId, скопированного из приложения
+const MAX_DEVICE_ID_LEN: usize = 128;
+
+fn sha1_hex(data: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(data.as_bytes());
+    let result = hasher.finalize();
+    format!("{:X}", result)
+}
+
+pub fn generate_device_id() -> String {
+    let random_data = rand::random::<u32>() as u32;
+    // Time since the epoch
+    let time_since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let random_data: String = format!("{}-{}", random_data, time_since_epoch);
+    sha1_hex(&random_data)
+}
+
+/// deviceId, однозначно определяемый `seed` (например, логином аккаунта):
+/// один и тот же seed на любом компьютере дает один и тот же deviceId.
+pub fn device_id_from_seed(seed: &str) -> String {
+    sha1_hex(&format!("micommunity-device-{}", seed))
+}
+
+/// Проверяет deviceId, введенный вручную (например, из приложения Mi Community).
+/// Он попадает в Cookie, поэтому пробелы, `;` и `=` недопустимы.
+pub fn parse_device_id(input: &str) -> Result<String, String> {
+    let device_id = input.trim();
+    if device_id.is_empty() {
+        return Err("deviceId пустой".to_string());
+    }
+    if device_id.len() > MAX_DEVICE_ID_LEN {
+        return Err(format!("deviceId длиннее {} символов", MAX_DEVICE_ID_LEN));
+    }
+    if let Some(c) = device_id
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':')))
+    {
+        return Err(format!("недопустимый символ в deviceId: {:?}", c));
+    }
+    Ok(device_id.to_string())
+}
@@ -0,0 +1,402 @@
+// engine.rs
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use chrono::{DateTime, TimeDelta};
+use chrono_tz::Tz;
+use reqwest::Client;
+use tracing::{Instrument, Level, Span, field::Empty, info_span};
+
+use crate::api::{CODE_REQUEST_REJECTED, CODE_TOKEN_EXPIRED, MiCommunityClient};
+use crate::burst::{BurstAttempt, decisive_attempt};
+use crate::clock::{Clock, SharedClock};
+use crate::config::Config;
+use crate::deadline::Deadline;
+use crate::events::EventSink;
+use crate::history::{AttemptRecord, HistoryStore, RequestRecord};
+use crate::network::SendStrategy;
+use crate::outcome::{ApplyOutcome, UnlockStatus};
+
+// Номер следующей подачи заявки для span `attempt`
+static NEXT_ATTEMPT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Span одной подачи заявки (`account` - аккаунт очереди). Поля `ping_ms` и
+/// `target_time` заполняются, когда становятся известны.
+pub(crate) fn attempt_span(account: Option<&str>) -> Span {
+    info_span!(
+        "attempt",
+        id = NEXT_ATTEMPT_ID.fetch_add(1, Ordering::Relaxed),
+        account,
+        ping_ms = Empty,
+        target_time = Empty
+    )
+}
+
+/// Движок подачи заявки. Весь прогресс сообщается через [`EventSink`].
+#[derive(Clone)]
+pub struct Engine {
+    pub(crate) client: MiCommunityClient,
+    pub(crate) config: Config,
+    pub(crate) strategy: Arc<dyn SendStrategy>,
+    pub(crate) events: Arc<dyn EventSink>,
+    pub(crate) history: Option<HistoryStore>,
+    /// Аккаунт, под названием которого попытки попадают в историю.
+    pub(crate) account: Option<String>,
+    /// Span аккаунта очереди, в котором выполняются его задачи.
+    pub(crate) span: Span,
+}
+
+impl Engine {
+    pub fn new(session: Client, events: Arc<dyn EventSink>) -> Self {
+        Engine::with_config(session, events, Config::default())
+    }
+
+    pub fn with_config(session: Client, events: Arc<dyn EventSink>, config: Config) -> Self {
+        Engine {
+            client: MiCommunityClient::with_base_url(session, &config.api.base_url),
+            strategy: config.schedule.strategy.build(),
+            config,
+            events,
+            history: None,
+            account: None,
+            span: Span::none(),
+        }
+    }
+
+    /// Заменяет стратегию из настроек собственной реализацией.
+    pub fn with_strategy(mut self, strategy: Arc<dyn SendStrategy>) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// История, по которой перед каждой подачей заново подбирается стратегия
+    /// из настроек (имеет смысл для `adaptive`).
+    pub fn with_history(mut self, history: HistoryStore) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Название аккаунта одиночного запуска для span `attempt` и истории.
+    pub fn with_account(mut self, label: &str) -> Self {
+        self.account = Some(label.to_string());
+        self
+    }
+
+    /// Копия движка со стратегией, подобранной по попыткам его аккаунта из
+    /// истории. Без истории - тот же движок.
+    pub fn learned(&self) -> Engine {
+        let mut engine = self.clone();
+        let Some(history) = &self.history else {
+            return engine;
+        };
+        let records = match history.load() {
+            Ok(records) => records,
+            Err(e) => {
+                self.log(format!(
+                    "Ошибка чтения истории {}: {}",
+                    history.path().display(),
+                    e
+                ));
+                vec![]
+            }
+        };
+        let records: Vec<AttemptRecord> = records
+            .into_iter()
+            .filter(|record| record.account == self.account)
+            .collect();
+        engine.strategy = self.config.schedule.strategy.build_with_history(&records);
+        engine
+    }
+
+    pub(crate) fn log<T: Display>(&self, message: T) {
+        self.events.event(Level::INFO, &message.to_string());
+    }
+
+    /// Подробности, которые нужны только для разбора (отдельные замеры).
+    pub(crate) fn debug<T: Display>(&self, message: T) {
+        self.events.event(Level::DEBUG, &message.to_string());
+    }
+
+    /// Сбой, после которого работа продолжается.
+    pub(crate) fn warn<T: Display>(&self, message: T) {
+        self.events.event(Level::WARN, &message.to_string());
+    }
+
+    /// Ошибка, из-за которой заявка не подана или не прошла.
+    pub(crate) fn error<T: Display>(&self, message: T) {
+        self.events.event(Level::ERROR, &message.to_string());
+    }
+
+    pub(crate) fn update_status(&self, ready: bool, message: &str) {
+        self.events.status(ready, message);
+    }
+
+    pub(crate) fn report_deadline(&self, title: &str, deadline: &Option<Deadline>) {
+        if let Some(deadline) = deadline
+            && deadline.date.is_some()
+        {
+            self.events.deadline(title, deadline);
+        }
+    }
+
+    pub(crate) fn record(&self, mut record: AttemptRecord) {
+        record.account = self.account.clone();
+        self.events.attempt(&record);
+    }
+
+    /// Подает заявку к ближайшей полуночи. `clock` нужен до синхронизации
+    /// времени: по нему разбираются даты из ответа статуса.
+    pub async fn submit(
+        &self,
+        clock: &dyn Clock,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> ApplyOutcome {
+        self.learned()
+            .submit_learned(clock, cookie_value, device_id)
+            .instrument(attempt_span(self.account.as_deref()))
+            .await
+    }
+
+    async fn submit_learned(
+        &self,
+        clock: &dyn Clock,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> ApplyOutcome {
+        if let Err(outcome) = self.check_eligible(clock, cookie_value, device_id).await {
+            self.record(AttemptRecord::new(&outcome));
+            return outcome;
+        }
+
+        let Some(clock) = self.sync_clock().await else {
+            self.error("Ошибка получения начального времени");
+            self.record(AttemptRecord::new(&ApplyOutcome::TimeSyncFailed));
+            return ApplyOutcome::TimeSyncFailed;
+        };
+        let synced = SharedClock::new(clock);
+        let resync = self.spawn_resync(synced.clone());
+        let clock: Arc<dyn Clock> = Arc::new(synced);
+        let latency = self.wait_until_ping_time(clock.as_ref()).await;
+        let target_time = self.target_time(clock.as_ref(), latency);
+        let warm_addr = self
+            .warm_up_connection(clock.as_ref(), target_time, cookie_value, device_id)
+            .await;
+        resync.abort();
+        self.apply(
+            &clock,
+            target_time,
+            latency,
+            cookie_value,
+            device_id,
+            warm_addr,
+        )
+        .await
+    }
+
+    /// Проверяет статус аккаунта. `Err` - итог без подачи заявки.
+    pub async fn check_eligible(
+        &self,
+        clock: &dyn Clock,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> Result<(), ApplyOutcome> {
+        match self
+            .check_unlock_status(clock, cookie_value, device_id)
+            .await
+        {
+            UnlockStatus::CanApply => Ok(()),
+            UnlockStatus::Approved { deadline } => {
+                self.log("[Статус] Заявка уже одобрена, подавать повторно не нужно.");
+                Err(ApplyOutcome::Approved { deadline })
+            }
+            UnlockStatus::TokenExpired => Err(ApplyOutcome::TokenExpired),
+            status => {
+                self.warn("[Статус] Ошибка, заявка отклонена или не подана.");
+                Err(ApplyOutcome::NotEligible(status))
+            }
+        }
+    }
+
+    /// Отправляет серию заявок к `target_time` и возвращает итог. Попытка с
+    /// замерами (`latency_ms` - измеренная задержка) уходит в историю.
+    pub async fn apply(
+        &self,
+        clock: &Arc<dyn Clock>,
+        target_time: DateTime<Tz>,
+        latency_ms: f64,
+        cookie_value: &str,
+        device_id: &str,
+        warm_addr: Option<SocketAddr>,
+    ) -> ApplyOutcome {
+        let attempts = self
+            .send_burst(clock, target_time, cookie_value, device_id, warm_addr)
+            .await;
+        let decisive = decisive_attempt(&attempts);
+        let outcome = match decisive {
+            Some(attempt) => {
+                if attempts.len() > 1 {
+                    self.log(format!(
+                        "Итог определяется по запросу {}/{}",
+                        attempt.index + 1,
+                        attempts.len()
+                    ));
+                }
+                self.resolve_outcome(clock.as_ref(), attempt, cookie_value, device_id)
+                    .await
+            }
+            None => {
+                self.error("Ни на один запрос не получен ответ");
+                let error = attempts
+                    .iter()
+                    .find_map(|a| a.error.clone())
+                    .unwrap_or_default();
+                ApplyOutcome::RequestFailed(error)
+            }
+        };
+
+        self.record(AttemptRecord {
+            ntp_offset_ms: clock.synced().map(|synced| synced.get().offset_ms()),
+            latency_ms: Some(latency_ms),
+            target_time: Some(target_time.fixed_offset()),
+            strategy: Some(self.strategy.describe()),
+            requests: attempts.iter().map(RequestRecord::from).collect(),
+            decisive: decisive.map(|attempt| attempt.index),
+            ..AttemptRecord::new(&outcome)
+        });
+        outcome
+    }
+
+    /// Итог по решающему ответу серии.
+    pub async fn resolve_outcome(
+        &self,
+        clock: &dyn Clock,
+        attempt: &BurstAttempt,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> ApplyOutcome {
+        let response_time = attempt.received.unwrap_or(attempt.sent);
+        let deadline = Deadline::from_api(
+            attempt.deadline_format.as_deref(),
+            response_time.date_naive(),
+        );
+        let deadline_format = deadline
+            .as_ref()
+            .map_or("Не указано".to_string(), |deadline| {
+                deadline.to_string()
+            });
+        match attempt.code {
+            Some(0) => match attempt.apply_result.unwrap_or(0) {
+                1 => {
+                    self.log("[Статус] Заявка одобрена, проверяем статус...");
+                    let deadline = match self
+                        .check_unlock_status(clock, cookie_value, device_id)
+                        .await
+                    {
+                        UnlockStatus::Approved { deadline } => deadline,
+                        _ => deadline,
+                    };
+                    ApplyOutcome::Approved { deadline }
+                }
+                3 => {
+                    self.warn(format!("[Статус] Заявка не подана, исчерпан лимит (Попробуйте привзять телефон в настройках в стасут Mi Unlock), попробуйте снова {}.", deadline_format));
+                    self.report_deadline("Новая попытка", &deadline);
+                    ApplyOutcome::QuotaExhausted { retry_at: deadline }
+                }
+                4 => {
+                    self.warn(format!("[Статус] apply_result 4: блокировка на подачу заявки до {}, проверяем время и статус...", deadline_format));
+                    self.apply_result_blocked(clock, attempt, deadline, cookie_value, device_id)
+                        .await
+                }
+                apply_result => {
+                    self.error(format!("[Статус] Неизвестный результат заявки (apply_result {}).", apply_result));
+                    ApplyOutcome::UnknownApplyResult(apply_result)
+                }
+            },
+            Some(CODE_REQUEST_REJECTED) => {
+                self.error("[Статус] Заявка отклонена, ошибка запроса (code 100001).");
+                ApplyOutcome::RequestRejected
+            }
+            Some(100003) => {
+                self.log("[Статус] Возможно заявка одобрена, проверяем статус... (code 100003).");
+                match self
+                    .check_unlock_status(clock, cookie_value, device_id)
+                    .await
+                {
+                    UnlockStatus::Approved { deadline } => ApplyOutcome::Approved { deadline },
+                    _ => ApplyOutcome::UnknownCode(100003),
+                }
+            }
+            Some(CODE_TOKEN_EXPIRED) => {
+                self.error("Cookie (токен) устарел, обновите. (code 100004)");
+                ApplyOutcome::TokenExpired
+            }
+            Some(code) => {
+                self.error(format!("[Статус] Неизвестный ответ сервера (code {}).", code));
+                ApplyOutcome::UnknownCode(code)
+            }
+            None => ApplyOutcome::RequestFailed(attempt.error.clone().unwrap_or_default()),
+        }
+    }
+
+    /// apply_result 4: блокировка выдана, но запрос, дошедший к самому сбросу
+    /// лимита, мог быть принят. Время получения запроса сервером оцениваем по
+    /// синхронизированным часам и заголовку `Date`, итог подтверждаем статусом.
+    async fn apply_result_blocked(
+        &self,
+        clock: &dyn Clock,
+        attempt: &BurstAttempt,
+        deadline: Option<Deadline>,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> ApplyOutcome {
+        let reset_offset = attempt.reset_offset();
+        match reset_offset {
+            Some(offset) if offset < TimeDelta::zero() => self.log(format!(
+                "Сервер получил запрос за {} мс до сброса лимита",
+                -offset.num_milliseconds()
+            )),
+            Some(offset) => self.log(format!(
+                "Сервер получил запрос через {} мс после сброса лимита",
+                offset.num_milliseconds()
+            )),
+            None => self.log("Время получения запроса сервером неизвестно"),
+        }
+        let accept_window =
+            TimeDelta::from_std(self.config.schedule.accept_window).unwrap_or(TimeDelta::zero());
+        let in_time = reset_offset.is_some_and(|offset| offset.abs() <= accept_window);
+
+        let blocked_until = match self
+            .check_unlock_status(clock, cookie_value, device_id)
+            .await
+        {
+            UnlockStatus::Approved { deadline } => {
+                self.log("Статус подтверждает: заявка одобрена");
+                return ApplyOutcome::Approved { deadline };
+            }
+            UnlockStatus::TokenExpired => return ApplyOutcome::TokenExpired,
+            UnlockStatus::Blocked { until } => {
+                self.warn("Статус подтверждает блокировку, заявка подана слишком поздно");
+                until.or(deadline)
+            }
+            _ if in_time => {
+                self.log("Ваша заявка была принята, зайдите в настройки телефона для привязки");
+                self.update_status(true, "Заявка принята");
+                return ApplyOutcome::Accepted;
+            }
+            _ => {
+                self.warn("Не удача, заявка подана слишком поздно");
+                deadline
+            }
+        };
+        self.update_status(false, "Ошибка");
+        self.report_deadline("Блокировка снимется", &blocked_until);
+        ApplyOutcome::TooLate { blocked_until }
+    }
+}
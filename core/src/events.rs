@@ -0,0 +1,35 @@
+// events.rs
+// Куда движок сообщает о ходе работы: окно, лог, собственный инструмент.
+use tracing::Level;
+
+use crate::deadline::Deadline;
+use crate::history::AttemptRecord;
+
+/// Получатель сообщений движка.
+pub trait EventSink: Send + Sync {
+    /// Строка лога (без временной метки).
+    fn log(&self, message: &str);
+
+    /// Строка лога с уровнем важности. По умолчанию уровень отбрасывается и
+    /// строка уходит в [`log`](EventSink::log).
+    fn event(&self, _level: Level, message: &str) {
+        self.log(message);
+    }
+
+    /// Краткий статус для индикатора в интерфейсе.
+    fn status(&self, _ready: bool, _message: &str) {}
+
+    /// Дата из ответа сервера (блокировка, лимит, срок одобрения) для
+    /// обратного отсчета в интерфейсе. `title` - что наступит к этой дате.
+    fn deadline(&self, _title: &str, _deadline: &Deadline) {}
+
+    /// Завершенная попытка подачи заявки с замерами времени (для истории).
+    fn attempt(&self, _record: &AttemptRecord) {}
+}
+
+/// Отбрасывает все сообщения.
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn log(&self, _message: &str) {}
+}
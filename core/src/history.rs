@@ -0,0 +1,189 @@
+// history.rs
+// История попыток: каждая подача заявки с замерами времени, по одной записи
+// JSON на строку. По ней подбирается стратегия отправки.
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use crate::burst::BurstAttempt;
+use crate::clock::{Clock, SystemClock};
+use crate::outcome::ApplyOutcome;
+
+/// Один запрос серии.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub index: usize,
+    pub scheduled: DateTime<FixedOffset>,
+    pub sent: DateTime<FixedOffset>,
+    pub received: Option<DateTime<FixedOffset>>,
+    /// Время сервера из заголовка `Date`.
+    pub server_date: Option<DateTime<FixedOffset>>,
+    pub http_status: Option<u16>,
+    pub code: Option<i64>,
+    pub apply_result: Option<i64>,
+    /// На сколько мс запрос дошел до сервера позже (> 0) или раньше (< 0) сброса лимита.
+    pub reset_offset_ms: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl From<&BurstAttempt> for RequestRecord {
+    fn from(attempt: &BurstAttempt) -> Self {
+        RequestRecord {
+            index: attempt.index,
+            scheduled: attempt.scheduled.fixed_offset(),
+            sent: attempt.sent.fixed_offset(),
+            received: attempt.received.map(|time| time.fixed_offset()),
+            server_date: attempt.server_date.map(|time| time.fixed_offset()),
+            http_status: attempt.http_status,
+            code: attempt.code,
+            apply_result: attempt.apply_result,
+            reset_offset_ms: attempt
+                .reset_offset()
+                .map(|offset| offset.num_milliseconds()),
+            error: attempt.error.clone(),
+        }
+    }
+}
+
+/// Одна попытка подачи заявки. Поля замеров пусты, если до них не дошло
+/// (например, аккаунт не может подать заявку).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    /// Название аккаунта (`None` - запуск без названия аккаунта).
+    pub account: Option<String>,
+    /// Когда записана попытка (пекинское время).
+    pub recorded_at: DateTime<FixedOffset>,
+    /// Смещение системных часов относительно NTP, мс.
+    pub ntp_offset_ms: Option<f64>,
+    /// Измеренная задержка до сервера, мс.
+    pub latency_ms: Option<f64>,
+    /// Расчетный момент отправки.
+    pub target_time: Option<DateTime<FixedOffset>>,
+    /// Описание стратегии отправки.
+    pub strategy: Option<String>,
+    pub requests: Vec<RequestRecord>,
+    /// Номер запроса, по которому определен итог.
+    pub decisive: Option<usize>,
+    /// Итог для человека.
+    pub outcome: String,
+    /// Заявка одобрена или принята.
+    pub success: bool,
+}
+
+impl AttemptRecord {
+    /// Запись без замеров: только итог.
+    pub fn new(outcome: &ApplyOutcome) -> Self {
+        AttemptRecord {
+            account: None,
+            recorded_at: SystemClock.now().fixed_offset(),
+            ntp_offset_ms: None,
+            latency_ms: None,
+            target_time: None,
+            strategy: None,
+            requests: vec![],
+            decisive: None,
+            outcome: outcome.to_string(),
+            success: outcome.is_success(),
+        }
+    }
+
+    /// Решающий запрос серии.
+    pub fn decisive_request(&self) -> Option<&RequestRecord> {
+        let index = self.decisive?;
+        self.requests.iter().find(|request| request.index == index)
+    }
+}
+
+impl fmt::Display for AttemptRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.recorded_at.format("%Y-%m-%d %H:%M:%S"))?;
+        if let Some(account) = &self.account {
+            write!(f, " [{}]", account)?;
+        }
+        write!(f, " {}", self.outcome)?;
+        if let Some(latency_ms) = self.latency_ms {
+            write!(f, "; пинг {:.0} мс", latency_ms)?;
+        }
+        if let Some(ntp_offset_ms) = self.ntp_offset_ms {
+            write!(f, "; NTP {:+.1} мс", ntp_offset_ms)?;
+        }
+        if let Some(target_time) = self.target_time {
+            write!(f, "; цель {}", target_time.format("%H:%M:%S%.3f"))?;
+        }
+        if let Some(request) = self.decisive_request() {
+            write!(f, "; отправлен {}", request.sent.format("%H:%M:%S%.3f"))?;
+            if let Some(received) = request.received {
+                write!(f, ", ответ {}", received.format("%H:%M:%S%.3f"))?;
+            }
+            if let Some(reset_offset_ms) = request.reset_offset_ms {
+                write!(f, ", от сброса {:+} мс", reset_offset_ms)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Файл истории (JSON Lines).
+#[derive(Debug, Clone)]
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        HistoryStore { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Дописывает запись в конец файла, создавая папку при необходимости.
+    pub fn append(&self, record: &AttemptRecord) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_string(record).map_err(io::Error::other)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    /// Все записи по порядку. Поврежденные строки пропускаются, нет файла -
+    /// пустая история.
+    pub fn load(&self) -> io::Result<Vec<AttemptRecord>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Последние `limit` попыток (0 - все), новые первыми. `account` - только
+    /// попытки этого аккаунта.
+    pub fn recent(&self, account: Option<&str>, limit: usize) -> io::Result<Vec<AttemptRecord>> {
+        let records = self
+            .load()?
+            .into_iter()
+            .rev()
+            .filter(|record| account.is_none() || record.account.as_deref() == account);
+        Ok(if limit == 0 {
+            records.collect()
+        } else {
+            records.take(limit).collect()
+        })
+    }
+}
@@ -0,0 +1,185 @@
+// latency.rs
+// Оценка задержки до API по HTTPS: ICMP требует прав, часто блокируется и
+// не отражает время, за которое POST доходит до сервера.
+use std::time::{Duration, Instant};
+
+use native_tls::TlsConnector;
+use tokio::{net::TcpStream, time::timeout};
+
+use crate::api::STATE_PATH;
+use crate::engine::Engine;
+
+const LATENCY_ATTEMPTS: usize = 3;
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Задержка по умолчанию, если измерить не удалось.
+pub const DEFAULT_LATENCY_MS: f64 = 150.0;
+
+/// Результат измерения задержки (медианы по нескольким попыткам), мс.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyEstimate {
+    pub tcp_connect_ms: Option<f64>,
+    pub tls_handshake_ms: Option<f64>,
+    /// Запрос по уже открытому соединению из пула `reqwest::Client`.
+    pub request_ms: Option<f64>,
+}
+
+impl LatencyEstimate {
+    /// Задержка, по которой рассчитывается время отправки.
+    pub fn effective_ms(&self) -> Option<f64> {
+        self.request_ms.or(self.tcp_connect_ms)
+    }
+}
+
+/// Медиана замеров, `None` для пустого списка.
+pub fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2f64)
+    } else {
+        Some(values[middle])
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000f64
+}
+
+/// Время установки TCP-соединения и TLS-рукопожатия (без учета DNS).
+/// Для HTTP без TLS время рукопожатия нулевое.
+async fn measure_handshake(
+    host: &str,
+    port: u16,
+    tls: bool,
+) -> Result<(Duration, Duration), String> {
+    let addr = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| "адрес не найден".to_string())?;
+
+    let start = Instant::now();
+    let stream = timeout(LATENCY_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| "таймаут TCP".to_string())?
+        .map_err(|e| e.to_string())?;
+    let tcp_connect = start.elapsed();
+    if !tls {
+        return Ok((tcp_connect, Duration::ZERO));
+    }
+
+    let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+    let start = Instant::now();
+    timeout(LATENCY_TIMEOUT, connector.connect(host, stream))
+        .await
+        .map_err(|_| "таймаут TLS".to_string())?
+        .map_err(|e| e.to_string())?;
+    let tls_handshake = start.elapsed();
+
+    Ok((tcp_connect, tls_handshake))
+}
+
+impl Engine {
+    async fn time_request(&self) -> Result<Duration, String> {
+        // Легкий запрос к тому же API: без cookie сервер сразу отвечает ошибкой
+        let start = Instant::now();
+        let response = self
+            .client
+            .session()
+            .get(self.client.url(STATE_PATH))
+            .timeout(LATENCY_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        response.bytes().await.map_err(|e| e.to_string())?;
+        Ok(start.elapsed())
+    }
+
+    pub async fn estimate_latency(&self) -> LatencyEstimate {
+        let Some((host, port, tls)) = self.client.endpoint() else {
+            self.error(format!("Некорректный адрес API: {}", self.client.base_url()));
+            return LatencyEstimate::default();
+        };
+        self.log(format!("Измеряем задержку до {} по HTTPS...", host));
+
+        let mut tcp_connect = vec![];
+        let mut tls_handshake = vec![];
+        for attempt in 0..LATENCY_ATTEMPTS {
+            match measure_handshake(&host, port, tls).await {
+                Ok((tcp, handshake)) => {
+                    tcp_connect.push(ms(tcp));
+                    if tls {
+                        tls_handshake.push(ms(handshake));
+                    }
+                }
+                Err(e) => {
+                    self.debug(format!(
+                        "Замер соединения {}/{} не удался: {}",
+                        attempt + 1,
+                        LATENCY_ATTEMPTS,
+                        e
+                    ));
+                }
+            }
+        }
+
+        // Первый запрос открывает соединение в пуле, остальные идут по нему
+        if let Err(e) = self.time_request().await {
+            self.debug(format!("Прогревочный запрос не удался: {}", e));
+        }
+        let mut requests = vec![];
+        for attempt in 0..LATENCY_ATTEMPTS {
+            match self.time_request().await {
+                Ok(duration) => requests.push(ms(duration)),
+                Err(e) => {
+                    self.debug(format!(
+                        "HTTPS-запрос {}/{} не удался: {}",
+                        attempt + 1,
+                        LATENCY_ATTEMPTS,
+                        e
+                    ));
+                }
+            }
+        }
+
+        LatencyEstimate {
+            tcp_connect_ms: median(tcp_connect),
+            tls_handshake_ms: median(tls_handshake),
+            request_ms: median(requests),
+        }
+    }
+
+    /// Задержка для расчета времени отправки, мс. При неудаче - значение по умолчанию.
+    pub async fn measure_latency_ms(&self) -> f64 {
+        let estimate = self.estimate_latency().await;
+        let format = |value: Option<f64>| match value {
+            Some(value) => format!("{:.1} мс", value),
+            None => "-".to_string(),
+        };
+        self.log(format!(
+            "TCP: {}, TLS: {}, HTTPS-запрос: {}",
+            format(estimate.tcp_connect_ms),
+            format(estimate.tls_handshake_ms),
+            format(estimate.request_ms)
+        ));
+        match estimate.effective_ms() {
+            Some(latency) => {
+                self.log(format!("Задержка для расчета: {:.1} мс", latency));
+                latency
+            }
+            None => {
+                self.warn("Не удалось измерить задержку до сервера!");
+                self.warn(format!(
+                    "Используем значение по умолчанию: {}мс",
+                    DEFAULT_LATENCY_MS
+                ));
+                DEFAULT_LATENCY_MS
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+// lib.rs
+//! Движок Mi Community Auto Unlock: проверка статуса, синхронизация времени,
+//! оценка пинга и подача заявки, без зависимости от интерфейса.
+pub mod accounts;
+pub mod adaptive;
+pub mod api;
+pub mod burst;
+pub mod clock;
+pub mod config;
+pub mod daemon;
+pub mod deadline;
+pub mod deviceid;
+pub mod engine;
+pub mod events;
+pub mod history;
+pub mod latency;
+pub mod network;
+pub mod ntp;
+pub mod outcome;
+
+pub use accounts::{Account, AccountOutcome};
+pub use api::{ApiError, MiCommunityClient};
+pub use clock::{Clock, FakeClock, SharedClock, SyncedClock, SystemClock};
+pub use config::{
+    ApiConfig, BurstConfig, Config, NtpConfig, RetryConfig, ScheduleConfig, StrategyConfig,
+};
+pub use deadline::Deadline;
+pub use engine::Engine;
+pub use events::{EventSink, NullSink};
+pub use history::{AttemptRecord, HistoryStore, RequestRecord};
+pub use network::SendStrategy;
+pub use outcome::{ApplyOutcome, UnlockStatus};
@@ -0,0 +1,394 @@
+use crate::api::{ApiError, CODE_TOKEN_EXPIRED, MiCommunityClient};
+use crate::clock::Clock;
+use crate::deadline::Deadline;
+use crate::engine::Engine;
+use crate::outcome::UnlockStatus;
+use std::{
+    f64,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use hyper::client::connect::HttpInfo;
+use reqwest::Response;
+use tracing::{Span, field::display};
+
+const FINAL_RESYNC_LEAD: TimeDelta = TimeDelta::seconds(60);
+// За сколько до отправки прогревать соединение
+const WARMUP_LEAD: TimeDelta = TimeDelta::seconds(2);
+const WARMUP_TIMEOUT: Duration = Duration::from_millis(1500);
+// Задержка, при которой формула дает 23:59:59.091: по ней время отправки
+// оценивается до измерения
+const NOMINAL_LATENCY_MS: f64 = 166f64;
+
+/// Локальный адрес TCP-соединения, по которому пришел ответ. Совпадение
+/// адресов у двух ответов означает, что соединение из пула переиспользовано.
+pub fn connection_local_addr(response: &Response) -> Option<SocketAddr> {
+    response
+        .extensions()
+        .get::<HttpInfo>()
+        .map(|info| info.local_addr())
+}
+
+fn calculate_script_time(ping: f64) -> f64 {
+    59.091 + (166f64 - ping) * 0.006
+}
+
+fn seconds(seconds: f64) -> TimeDelta {
+    TimeDelta::microseconds((seconds * 1_000_000f64).round() as i64)
+}
+
+/// Ближайшая полночь по Пекину после `time`.
+pub fn next_midnight(time: DateTime<Tz>) -> DateTime<Tz> {
+    let date = time.date_naive() + TimeDelta::days(1);
+    Shanghai
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .unwrap()
+}
+
+/// Полночь по Пекину, ближайшая к `time` (до или после).
+pub fn nearest_midnight(time: DateTime<Tz>) -> DateTime<Tz> {
+    let next = next_midnight(time);
+    let previous = next - TimeDelta::days(1);
+    if next - time < time - previous {
+        next
+    } else {
+        previous
+    }
+}
+
+/// Как выбрать момент отправки заявки относительно полуночи по Пекину.
+pub trait SendStrategy: Send + Sync {
+    /// Момент отправки для полуночи `midnight` при задержке до сервера `latency_ms`.
+    fn target_time(&self, midnight: DateTime<Tz>, latency_ms: f64) -> DateTime<Tz>;
+
+    /// Описание для лога.
+    fn describe(&self) -> String;
+}
+
+/// Исходная эмпирическая формула: 23:59:59.091 + (166 - задержка) * 0.006 с.
+pub struct FormulaStrategy;
+
+impl SendStrategy for FormulaStrategy {
+    fn target_time(&self, midnight: DateTime<Tz>, latency_ms: f64) -> DateTime<Tz> {
+        midnight - TimeDelta::seconds(60) + seconds(calculate_script_time(latency_ms))
+    }
+
+    fn describe(&self) -> String {
+        "формула 59.091 + (166 - задержка) * 0.006".to_string()
+    }
+}
+
+/// Фиксированное время до полуночи, без учета задержки.
+pub struct FixedOffsetStrategy {
+    pub before_midnight: Duration,
+}
+
+impl SendStrategy for FixedOffsetStrategy {
+    fn target_time(&self, midnight: DateTime<Tz>, _latency_ms: f64) -> DateTime<Tz> {
+        midnight - TimeDelta::from_std(self.before_midnight).unwrap_or(TimeDelta::zero())
+    }
+
+    fn describe(&self) -> String {
+        format!("за {} мс до полуночи", self.before_midnight.as_millis())
+    }
+}
+
+/// Отправка за половину задержки до полуночи, чтобы запрос пришел ровно в 00:00,
+/// плюс поправка `bias_ms` (может быть отрицательной).
+pub struct HalfRttStrategy {
+    pub bias_ms: i64,
+}
+
+impl SendStrategy for HalfRttStrategy {
+    fn target_time(&self, midnight: DateTime<Tz>, latency_ms: f64) -> DateTime<Tz> {
+        midnight - seconds(latency_ms / 2000f64) + TimeDelta::milliseconds(self.bias_ms)
+    }
+
+    fn describe(&self) -> String {
+        format!("половина задержки, поправка {} мс", self.bias_ms)
+    }
+}
+
+/// Заданное пользователем время суток (Пекинское). Время после полудня
+/// относится к дню перед полуночью, до полудня - к дню после нее.
+pub struct FixedTimeStrategy {
+    pub time: NaiveTime,
+}
+
+impl SendStrategy for FixedTimeStrategy {
+    fn target_time(&self, midnight: DateTime<Tz>, _latency_ms: f64) -> DateTime<Tz> {
+        let mut date = midnight.date_naive();
+        if self.time >= NaiveTime::from_hms_opt(12, 0, 0).unwrap() {
+            date -= TimeDelta::days(1);
+        }
+        Shanghai.from_local_datetime(&date.and_time(self.time)).unwrap()
+    }
+
+    fn describe(&self) -> String {
+        format!("в {}", self.time.format("%H:%M:%S%.3f"))
+    }
+}
+
+impl Engine {
+    /// Момент отправки по стратегии для полуночи, к которой он еще впереди:
+    /// сразу после полуночи это может быть еще она. Прошедший момент
+    /// возвращается, только если стратегия отправляет раньше `current_time`.
+    fn strategy_target(&self, current_time: DateTime<Tz>, latency_ms: f64) -> DateTime<Tz> {
+        let midnight = next_midnight(current_time);
+        let target_time = self
+            .strategy
+            .target_time(midnight - TimeDelta::days(1), latency_ms);
+        if target_time > current_time {
+            return target_time;
+        }
+        self.strategy.target_time(midnight, latency_ms)
+    }
+
+    /// Время отправки заявки (Пекинское) по выбранной стратегии.
+    pub fn target_time(&self, clock: &dyn Clock, latency_ms: f64) -> DateTime<Tz> {
+        let current_time = clock.now();
+        let target_time = self.strategy_target(current_time, latency_ms);
+        if target_time <= current_time {
+            self.warn(format!(
+                "Время отправки {} уже прошло, запросы уйдут сразу: стратегия отправляет раньше, чем закончилось измерение задержки",
+                target_time
+            ));
+        }
+
+        Span::current()
+            .record("ping_ms", latency_ms)
+            .record("target_time", display(target_time));
+        self.log(format!("Стратегия отправки: {}", self.strategy.describe()));
+        self.log(format!(
+            "Ожидание до {} (задержка {:.1} мс) (Пекинское время)",
+            target_time, latency_ms
+        ));
+        self.debug(format!(
+            "Местное время: {}",
+            Local.from_utc_datetime(&target_time.naive_utc())
+        ));
+        target_time
+    }
+
+    /// Ждет наступления `target_time` по синхронизированным часам.
+    pub async fn wait_until(&self, clock: &dyn Clock, target_time: DateTime<Tz>) {
+        loop {
+            let current_time: DateTime<Tz> = clock.now();
+            let time_difference: TimeDelta = target_time.with_timezone(&Shanghai) - current_time;
+            let secs = time_difference.num_microseconds().unwrap_or(i64::MAX) as f64 / 1_000_000f64;
+            if secs > 0.1f64 {
+                let dur = Duration::from_secs_f64(secs * 0.9);
+                tokio::time::sleep(dur).await;
+            } else if secs > 0.0f64 {
+                let dur = Duration::from_secs_f64(secs);
+                tokio::time::sleep(dur).await;
+            } else if current_time >= target_time {
+                break;
+            }
+        }
+    }
+
+    pub async fn wait_until_target_time(&self, clock: &dyn Clock, target_time: DateTime<Tz>) {
+        self.wait_until(clock, target_time).await;
+        self.log(format!(
+            "Время достигнуто: {}. Начинает отправку запросов",
+            target_time
+        ));
+    }
+
+    /// Незадолго до `target_time` открывает соединение к API дешевым запросом
+    /// к bl-switch/state, чтобы POST заявки не тратил время на DNS, TCP и TLS.
+    /// Возвращает локальный адрес прогретого соединения.
+    pub async fn warm_up_connection(
+        &self,
+        clock: &dyn Clock,
+        target_time: DateTime<Tz>,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> Option<SocketAddr> {
+        if target_time - clock.now() < WARMUP_LEAD {
+            self.log("До отправки слишком мало времени, прогрев соединения пропущен");
+            return None;
+        }
+        self.wait_until(clock, target_time - WARMUP_LEAD).await;
+
+        let started = Instant::now();
+        let request = self
+            .client
+            .state_request(cookie_value, device_id)
+            .header("Connection", "keep-alive")
+            .timeout(WARMUP_TIMEOUT);
+        let response = MiCommunityClient::send(request).await;
+        match response {
+            Ok(response) => {
+                let local_addr = connection_local_addr(&response);
+                // Тело дочитываем, иначе соединение не вернется в пул
+                let _ = response.bytes().await;
+                self.debug(format!(
+                    "Соединение прогрето за {:.1} мс (локальный адрес {})",
+                    started.elapsed().as_secs_f64() * 1000f64,
+                    local_addr.map_or("неизвестен".to_string(), |addr| addr.to_string())
+                ));
+                local_addr
+            }
+            Err(e) => {
+                self.warn(format!("Ошибка прогрева соединения: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Пишет в лог, ушел ли запрос по прогретому соединению.
+    pub fn log_connection_reuse(&self, warm_addr: Option<SocketAddr>, response: &Response) {
+        let Some(warm_addr) = warm_addr else {
+            return;
+        };
+        match connection_local_addr(response) {
+            Some(addr) if addr == warm_addr => {
+                self.debug("Запрос отправлен по прогретому соединению");
+            }
+            Some(addr) => {
+                self.warn(format!(
+                    "Прогретое соединение не переиспользовано ({} вместо {})",
+                    addr, warm_addr
+                ));
+            }
+            None => {
+                self.debug("Не удалось определить, переиспользовано ли соединение");
+            }
+        }
+    }
+
+    /// Статус аккаунта. Даты из ответа относятся к дню по часам `clock`.
+    pub async fn check_unlock_status(
+        &self,
+        clock: &dyn Clock,
+        cookie_value: &str,
+        device_id: &str,
+    ) -> UnlockStatus {
+        self.update_status(false, "Проверка статуса");
+        self.log("Проверяем статус разблокировки...");
+
+        let state = match self.client.bl_switch_state(cookie_value, device_id).await {
+            Ok(state) => {
+                self.debug("Ответ получен...");
+                state
+            }
+            Err(ApiError::Api {
+                code: CODE_TOKEN_EXPIRED,
+                ..
+            }) => {
+                self.update_status(false, "Ошибка");
+                self.error("Cookie (токен) устарел, обновите. (code 100004)");
+                return UnlockStatus::TokenExpired;
+            }
+            Err(ApiError::Api { code, .. }) => {
+                self.error(format!("Ошибка проверки статуса: code {}", code));
+                self.update_status(false, "Ошибка");
+                return UnlockStatus::UnknownCode(code);
+            }
+            Err(e) => {
+                self.error(format!("Ошибка проверки статуса: {}", e));
+                self.update_status(false, "Ошибка");
+                return UnlockStatus::RequestFailed(e.to_string());
+            }
+        };
+        let deadline = Deadline::from_api(
+            state.deadline_format.as_deref(),
+            clock.now().date_naive(),
+        );
+        let deadline_format = deadline
+            .as_ref()
+            .map_or("Не указано".to_string(), |deadline| {
+                deadline.to_string()
+            });
+
+        match (state.is_pass, state.button_state) {
+            (4, Some(1)) => {
+                self.log("[Статус] Аккаунт может подать заявку на разблокировку.");
+                self.update_status(true, "Можно разблокировать");
+                UnlockStatus::CanApply
+            }
+            (4, Some(2)) => {
+                self.log(format!(
+                    "[Статус] На аккаунте блокировка на подачу заявки до {}.",
+                    deadline_format
+                ));
+                self.update_status(false, "Заблокировано");
+                self.report_deadline("Блокировка снимется", &deadline);
+                UnlockStatus::Blocked { until: deadline }
+            }
+            (4, Some(3)) => {
+                self.log("[Статус] Аккаунт создан менее 30 дней назад.");
+                self.update_status(false, "Менее 30 дней");
+                UnlockStatus::TooYoung
+            }
+            (1, _) => {
+                self.log(format!(
+                    "[Статус] Заявка одобрена, разблокировка возможна до {}.",
+                    deadline_format
+                ));
+                self.update_status(true, "Одобрено");
+                self.report_deadline("Срок разблокировки истечет", &deadline);
+                UnlockStatus::Approved { deadline }
+            }
+            (is_pass, button_state) => {
+                self.error(format!(
+                    "[Статус] Ошибка получения статуса разблокировки (is_pass {}, button_state {}).",
+                    is_pass,
+                    button_state.map_or("-".to_string(), |b| b.to_string())
+                ));
+                self.update_status(false, "Ошибка");
+                UnlockStatus::UnknownState {
+                    is_pass,
+                    button_state,
+                }
+            }
+        }
+    }
+
+    /// Момент измерения задержки: `measure_before` до предварительного
+    /// времени отправки по стратегии, или сразу, если он уже прошел.
+    pub fn measure_time(&self, current_time: DateTime<Tz>) -> DateTime<Tz> {
+        let measure_before =
+            TimeDelta::from_std(self.config.schedule.measure_before).unwrap_or(TimeDelta::zero());
+        let target_time = self.strategy_target(current_time, NOMINAL_LATENCY_MS) - measure_before;
+        if current_time > target_time {
+            self.log("Время измерения задержки уже наступило, измеряем сразу");
+            return current_time;
+        }
+        target_time
+    }
+
+    /// Ждет момента измерения задержки и возвращает его.
+    pub async fn wait_until_measure_time(&self, clock: &dyn Clock) -> DateTime<Tz> {
+        let current_time = clock.now();
+        let target_time = self.measure_time(current_time);
+        self.log(format!(
+            "Ожидание до {} для измерения задержки (Пекинское время)",
+            target_time
+        ));
+        // Если ждать долго, еще раз сверяем часы незадолго до измерения пинга
+        if let Some(synced) = clock.synced()
+            && target_time - current_time > FINAL_RESYNC_LEAD
+        {
+            self.wait_until(clock, target_time - FINAL_RESYNC_LEAD).await;
+            self.log("Финальная пересинхронизация перед измерением пинга...");
+            self.resync(synced).await;
+        }
+        self.wait_until(clock, target_time).await;
+        self.log(format!(
+            "Время достигнуто: {}. Начинается измерение задержки",
+            target_time
+        ));
+        target_time
+    }
+
+    pub async fn wait_until_ping_time(&self, clock: &dyn Clock) -> f64 {
+        self.wait_until_measure_time(clock).await;
+        self.measure_latency_ms().await
+    }
+}
@@ -0,0 +1,345 @@
+// ntp.rs
+// Синхронизация времени: опрашиваем сразу несколько NTP-серверов и выбираем
+// смещение, с которым согласно большинство (алгоритм Марзулло).
+use std::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::TimeDelta;
+use sntpc::{NtpContext, StdTimestampGen, sync::get_time};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{Instrument, Span};
+
+use crate::clock::{SharedClock, SyncedClock};
+use crate::engine::Engine;
+
+/// Серверы по умолчанию.
+pub const DEFAULT_NTP_SERVERS: [&str; 11] = [
+    "time1.google.com:123",
+    "time2.google.com:123",
+    "time3.google.com:123",
+    "time4.google.com:123",
+    "time.android.com:123",
+    "time.aws.com:123",
+    "time.google.com:123",
+    "time.cloudflare.com:123",
+    "ntp.time.in.ua:123",
+    "stratum1.net:123",
+    "ntp5.stratum2.ru:123",
+];
+
+// Как часто сверять системные часы с монотонными
+const JUMP_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// Расхождение, после которого считаем, что часы перевели или был сон
+const JUMP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Ответ одного NTP-сервера.
+#[derive(Debug, Clone)]
+pub struct NtpSample {
+    pub server: String,
+    /// На сколько системные часы отстают от сервера.
+    pub offset: TimeDelta,
+    /// Задержка запроса туда и обратно.
+    pub delay: Duration,
+}
+
+impl NtpSample {
+    /// Интервал, в котором гарантированно лежит истинное смещение: offset ± delay/2.
+    fn interval(&self) -> (TimeDelta, TimeDelta) {
+        let half_delay = TimeDelta::from_std(self.delay / 2).unwrap_or(TimeDelta::zero());
+        (self.offset - half_delay, self.offset + half_delay)
+    }
+}
+
+/// Смещение, выбранное по ответам нескольких серверов.
+#[derive(Debug, Clone)]
+pub struct Consensus {
+    pub offset: TimeDelta,
+    /// Доверительный интервал смещения.
+    pub low: TimeDelta,
+    pub high: TimeDelta,
+    /// Минимальная задержка среди согласных серверов.
+    pub delay: Duration,
+    pub accepted: Vec<NtpSample>,
+    pub rejected: Vec<NtpSample>,
+}
+
+impl Consensus {
+    /// Половина ширины доверительного интервала.
+    pub fn margin(&self) -> TimeDelta {
+        (self.high - self.low) / 2
+    }
+
+    /// Разброс смещений среди согласных серверов.
+    pub fn spread(&self) -> TimeDelta {
+        let min = self.accepted.iter().map(|s| s.offset).min();
+        let max = self.accepted.iter().map(|s| s.offset).max();
+        match (min, max) {
+            (Some(min), Some(max)) => max - min,
+            _ => TimeDelta::zero(),
+        }
+    }
+}
+
+/// Почему не удалось выбрать смещение.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsensusError {
+    /// Ответило слишком мало серверов.
+    NotEnoughServers { answered: usize, required: usize },
+    /// Нет большинства серверов с пересекающимися интервалами.
+    NoMajority { agreeing: usize, answered: usize },
+    /// Согласные серверы расходятся сильнее допустимого.
+    Disagreement { spread: TimeDelta, limit: TimeDelta },
+}
+
+impl std::fmt::Display for ConsensusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConsensusError::NotEnoughServers { answered, required } => write!(
+                f,
+                "ответило {} NTP-серверов, нужно минимум {}",
+                answered, required
+            ),
+            ConsensusError::NoMajority { agreeing, answered } => write!(
+                f,
+                "согласны только {} из {} NTP-серверов",
+                agreeing, answered
+            ),
+            ConsensusError::Disagreement { spread, limit } => write!(
+                f,
+                "NTP-серверы расходятся на {} мс (допустимо {} мс)",
+                spread.num_milliseconds(),
+                limit.num_milliseconds()
+            ),
+        }
+    }
+}
+
+/// Выбирает смещение по алгоритму Марзулло: ищет отрезок, который покрыт
+/// интервалами наибольшего числа серверов, остальные серверы отбрасываются.
+pub fn consensus(
+    samples: Vec<NtpSample>,
+    min_servers: usize,
+    max_disagreement: Duration,
+) -> Result<Consensus, ConsensusError> {
+    let answered = samples.len();
+    if answered < min_servers.max(1) {
+        return Err(ConsensusError::NotEnoughServers {
+            answered,
+            required: min_servers.max(1),
+        });
+    }
+
+    // Начало интервала (-1) сортируется раньше конца (+1) в той же точке,
+    // чтобы касающиеся интервалы считались пересекающимися.
+    let mut edges: Vec<(TimeDelta, i32)> = vec![];
+    for sample in &samples {
+        let (low, high) = sample.interval();
+        edges.push((low, -1));
+        edges.push((high, 1));
+    }
+    edges.sort();
+
+    let mut best = 0;
+    let mut count = 0;
+    let mut low = TimeDelta::zero();
+    let mut high = TimeDelta::zero();
+    for (i, (edge, kind)) in edges.iter().enumerate() {
+        count -= kind;
+        if count > best {
+            // Начало интервала никогда не бывает последним краем
+            best = count;
+            low = *edge;
+            high = edges[i + 1].0;
+        }
+    }
+
+    let (accepted, rejected): (Vec<NtpSample>, Vec<NtpSample>) =
+        samples.into_iter().partition(|sample| {
+            let (sample_low, sample_high) = sample.interval();
+            sample_low <= low && sample_high >= high
+        });
+
+    if accepted.len() <= answered / 2 {
+        return Err(ConsensusError::NoMajority {
+            agreeing: accepted.len(),
+            answered,
+        });
+    }
+
+    let delay = accepted
+        .iter()
+        .map(|s| s.delay)
+        .min()
+        .unwrap_or(Duration::ZERO);
+    let consensus = Consensus {
+        offset: low + (high - low) / 2,
+        low,
+        high,
+        delay,
+        accepted,
+        rejected,
+    };
+
+    let limit = TimeDelta::from_std(max_disagreement).unwrap_or(TimeDelta::MAX);
+    if consensus.spread() > limit {
+        return Err(ConsensusError::Disagreement {
+            spread: consensus.spread(),
+            limit,
+        });
+    }
+    Ok(consensus)
+}
+
+fn query_server(server: &str, timeout: Duration) -> Result<NtpSample, String> {
+    let addr = server
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| "адрес не найден".to_string())?;
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    socket
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    let ntp_context = NtpContext::new(StdTimestampGen::default());
+    let time = get_time(addr, &socket, ntp_context).map_err(|e| format!("{:?}", e))?;
+    // Stratum 0 - Kiss-o'-Death: сервер просит его не опрашивать
+    if time.stratum() == 0 {
+        return Err("сервер отказал (Kiss-o'-Death)".to_string());
+    }
+    // offset и roundtrip у sntpc в микросекундах
+    Ok(NtpSample {
+        server: server.to_string(),
+        offset: TimeDelta::microseconds(time.offset()),
+        delay: Duration::from_micros(time.roundtrip()),
+    })
+}
+
+fn ms(delta: TimeDelta) -> f64 {
+    delta.num_microseconds().unwrap_or(0) as f64 / 1000f64
+}
+
+impl Engine {
+    /// Опрашивает все NTP-серверы параллельно.
+    pub async fn query_ntp_servers(&self) -> Vec<NtpSample> {
+        let timeout = self.config.ntp.timeout;
+        let mut tasks = JoinSet::new();
+        for server in self.config.ntp.servers.clone() {
+            tasks.spawn_blocking(move || {
+                let result = query_server(&server, timeout);
+                (server, result)
+            });
+        }
+
+        let mut samples = vec![];
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok((_, Ok(sample))) => {
+                    self.debug(format!(
+                        "NTP {}: смещение {:.3} мс, задержка {:.3} мс",
+                        sample.server,
+                        ms(sample.offset),
+                        sample.delay.as_secs_f64() * 1000f64
+                    ));
+                    samples.push(sample);
+                }
+                Ok((server, Err(e))) => {
+                    self.debug(format!("Ошибка подключения к {}: {}", server, e));
+                }
+                Err(e) => {
+                    self.debug(format!("Ошибка опроса NTP: {}", e));
+                }
+            }
+        }
+        samples
+    }
+
+    pub async fn sync_clock(&self) -> Option<SyncedClock> {
+        self.log("Синхронизация времени с NTP-серверами...");
+        let samples = self.query_ntp_servers().await;
+        let ntp = &self.config.ntp;
+        match consensus(samples, ntp.min_servers, ntp.max_disagreement) {
+            Ok(consensus) => {
+                for sample in &consensus.rejected {
+                    self.debug(format!(
+                        "Отброшен NTP-сервер {} (смещение {:.3} мс вне общего интервала)",
+                        sample.server,
+                        ms(sample.offset)
+                    ));
+                }
+                let clock = SyncedClock::new(consensus.offset, consensus.delay);
+                self.log(format!(
+                    "Выбрано смещение {:.3} мс ± {:.3} мс [{:.3}; {:.3}] ({} из {} серверов согласны)",
+                    ms(consensus.offset),
+                    ms(consensus.margin()),
+                    ms(consensus.low),
+                    ms(consensus.high),
+                    consensus.accepted.len(),
+                    consensus.accepted.len() + consensus.rejected.len()
+                ));
+                self.log(format!(
+                    "Пекинское время: {}",
+                    clock.now().format("%Y-%m-%d %H:%M:%S%.3f")
+                ));
+                Some(clock)
+            }
+            Err(e) => {
+                self.warn(format!("Синхронизация времени отклонена: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Повторная синхронизация: обновляет часы и пишет в лог накопившийся дрейф.
+    pub async fn resync(&self, clock: &SharedClock) -> bool {
+        let Some(new_clock) = self.sync_clock().await else {
+            self.warn("Пересинхронизация не удалась, продолжаем с прежним смещением");
+            return false;
+        };
+        let drift = new_clock.now() - clock.now();
+        clock.set(new_clock);
+        self.log(format!(
+            "Пересинхронизация: дрейф часов {:.3} мс",
+            ms(drift)
+        ));
+        true
+    }
+
+    /// Фоновая задача: пересинхронизирует часы раз в `resync_interval` и сразу
+    /// после скачка системных часов (перевод времени, сон/пробуждение).
+    pub fn spawn_resync(&self, clock: SharedClock) -> JoinHandle<()> {
+        let engine = self.clone();
+        let span = Span::current();
+        tokio::spawn(async move {
+            let interval = engine.config.ntp.resync_interval;
+            let mut last_sync = Instant::now();
+            let mut last_check = (Instant::now(), SystemTime::now());
+            loop {
+                tokio::time::sleep(JUMP_CHECK_INTERVAL).await;
+
+                let check = (Instant::now(), SystemTime::now());
+                let monotonic = check.0 - last_check.0;
+                let jump = match check.1.duration_since(last_check.1) {
+                    Ok(wall) => wall.abs_diff(monotonic),
+                    Err(e) => e.duration() + monotonic,
+                };
+                last_check = check;
+
+                let jumped = jump > JUMP_THRESHOLD;
+                if jumped {
+                    span.in_scope(|| {
+                        engine.log(format!(
+                            "Обнаружен скачок системных часов на {} мс, пересинхронизация...",
+                            jump.as_millis()
+                        ))
+                    });
+                }
+                if jumped || (!interval.is_zero() && last_sync.elapsed() >= interval) {
+                    engine.resync(&clock).instrument(span.clone()).await;
+                    last_sync = Instant::now();
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,145 @@
+// outcome.rs
+// Результаты проверки статуса и подачи заявки, по которым интерфейс, CLI и
+// уведомления принимают решения (логи остаются только для человека).
+use std::fmt;
+
+use crate::deadline::Deadline;
+
+fn or_unknown(date: &Option<Deadline>) -> String {
+    date.as_ref()
+        .map_or("не указано".to_string(), |date| date.to_string())
+}
+
+/// Статус аккаунта по `bl-switch/state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnlockStatus {
+    /// Заявка уже одобрена, разблокировка возможна до `deadline`.
+    Approved { deadline: Option<Deadline> },
+    /// Можно подавать заявку.
+    CanApply,
+    /// Подача заявок заблокирована до `until`.
+    Blocked { until: Option<Deadline> },
+    /// Аккаунт создан менее 30 дней назад.
+    TooYoung,
+    /// Токен устарел (code 100004).
+    TokenExpired,
+    /// Неизвестный код API.
+    UnknownCode(i64),
+    /// Неизвестная комбинация is_pass/button_state.
+    UnknownState { is_pass: i64, button_state: Option<i64> },
+    /// Запрос не удался (соединение, HTTP, формат ответа).
+    RequestFailed(String),
+}
+
+/// Итог попытки подачи заявки.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyOutcome {
+    /// Заявка одобрена (apply_result 1 или уже была одобрена).
+    Approved { deadline: Option<Deadline> },
+    /// apply_result 4, но запрос дошел вовремя: заявка, скорее всего, принята.
+    Accepted,
+    /// Лимит заявок на сегодня исчерпан (apply_result 3).
+    QuotaExhausted { retry_at: Option<Deadline> },
+    /// Запрос дошел слишком поздно, выдана блокировка (apply_result 4).
+    TooLate { blocked_until: Option<Deadline> },
+    /// Статус аккаунта не позволяет подать заявку.
+    NotEligible(UnlockStatus),
+    /// Токен устарел (code 100004).
+    TokenExpired,
+    /// Заявка отклонена, ошибка запроса (code 100001).
+    RequestRejected,
+    /// Не удалось синхронизировать время.
+    TimeSyncFailed,
+    /// Ни на один запрос не получен ответ.
+    RequestFailed(String),
+    /// Неизвестный apply_result при code 0.
+    UnknownApplyResult(i64),
+    /// Неизвестный код API.
+    UnknownCode(i64),
+}
+
+impl UnlockStatus {
+    /// Дата из `deadline_format`, если статус ее содержит.
+    pub fn deadline(&self) -> Option<&Deadline> {
+        match self {
+            UnlockStatus::Approved { deadline } => deadline.as_ref(),
+            UnlockStatus::Blocked { until } => until.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl ApplyOutcome {
+    /// Заявка одобрена или принята.
+    pub fn is_success(&self) -> bool {
+        matches!(self, ApplyOutcome::Approved { .. } | ApplyOutcome::Accepted)
+    }
+
+    /// Дата из `deadline_format`, если итог ее содержит.
+    pub fn deadline(&self) -> Option<&Deadline> {
+        match self {
+            ApplyOutcome::Approved { deadline } => deadline.as_ref(),
+            ApplyOutcome::QuotaExhausted { retry_at } => retry_at.as_ref(),
+            ApplyOutcome::TooLate { blocked_until } => blocked_until.as_ref(),
+            ApplyOutcome::NotEligible(status) => status.deadline(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UnlockStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnlockStatus::Approved { deadline } => {
+                write!(f, "одобрено до {}", or_unknown(deadline))
+            }
+            UnlockStatus::CanApply => write!(f, "можно подать заявку"),
+            UnlockStatus::Blocked { until } => {
+                write!(f, "заблокировано до {}", or_unknown(until))
+            }
+            UnlockStatus::TooYoung => write!(f, "аккаунт моложе 30 дней"),
+            UnlockStatus::TokenExpired => write!(f, "токен устарел"),
+            UnlockStatus::UnknownCode(code) => write!(f, "неизвестный код {}", code),
+            UnlockStatus::UnknownState {
+                is_pass,
+                button_state,
+            } => write!(
+                f,
+                "неизвестный статус (is_pass {}, button_state {})",
+                is_pass,
+                button_state.map_or("-".to_string(), |b| b.to_string())
+            ),
+            UnlockStatus::RequestFailed(e) => write!(f, "ошибка запроса: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for ApplyOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyOutcome::Approved { deadline } => {
+                write!(f, "одобрено до {}", or_unknown(deadline))
+            }
+            ApplyOutcome::Accepted => write!(f, "заявка принята"),
+            ApplyOutcome::QuotaExhausted { retry_at } => {
+                write!(f, "лимит исчерпан, снова в {}", or_unknown(retry_at))
+            }
+            ApplyOutcome::TooLate { blocked_until } => {
+                write!(
+                    f,
+                    "слишком поздно, блокировка до {}",
+                    or_unknown(blocked_until)
+                )
+            }
+            ApplyOutcome::NotEligible(status) => write!(f, "не подано: {}", status),
+            ApplyOutcome::TokenExpired => write!(f, "токен устарел"),
+            ApplyOutcome::RequestRejected => write!(f, "заявка отклонена (code 100001)"),
+            ApplyOutcome::TimeSyncFailed => write!(f, "не удалось синхронизировать время"),
+            ApplyOutcome::RequestFailed(e) => write!(f, "ошибка запроса: {}", e),
+            ApplyOutcome::UnknownApplyResult(result) => {
+                write!(f, "неизвестный apply_result {}", result)
+            }
+            ApplyOutcome::UnknownCode(code) => write!(f, "неизвестный код {}", code),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use std::sync::Arc;
+
+use micommunity_core::{Account, AccountOutcome, ApplyOutcome, UnlockStatus};
+use tracing::Level;
+
+use common::{
+    APPLY_PATH, MockServer, RecordingSink, STATE_PATH, clock, deadline, error_body, state_body,
+};
+
+fn accounts(labels: &[&str]) -> Vec<Account> {
+    labels
+        .iter()
+        .map(|label| Account {
+            label: label.to_string(),
+            cookie_value: format!("token-{}", label),
+            device_id: format!("device-{}", label),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn approved_accounts_skip_apply() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(1, None, Some("11/01")));
+
+    let results = server
+        .engine()
+        .submit_all(&clock(), &accounts(&["first", "second", "third"]))
+        .await;
+    let approved = ApplyOutcome::Approved {
+        deadline: deadline("11/01"),
+    };
+    assert_eq!(
+        results,
+        ["first", "second", "third"].map(|label| AccountOutcome {
+            label: label.to_string(),
+            outcome: approved.clone(),
+        })
+    );
+    assert_eq!(server.count(STATE_PATH), 3);
+    assert_eq!(server.count(APPLY_PATH), 0);
+
+    // Каждый аккаунт проверяется со своим токеном и deviceId
+    let mut cookies: Vec<String> = server
+        .requests()
+        .into_iter()
+        .filter_map(|r| r.cookie)
+        .collect();
+    cookies.sort();
+    assert_eq!(cookies.len(), 3);
+    for (cookie, label) in cookies.iter().zip(["first", "second", "third"]) {
+        assert!(cookie.contains(&format!("token-{}", label)), "{}", cookie);
+        assert!(cookie.contains(&format!("device-{}", label)), "{}", cookie);
+    }
+}
+
+#[tokio::test]
+async fn ineligible_accounts_report_status() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(4, Some(3), None));
+
+    let results = server
+        .engine()
+        .submit_all(&clock(), &accounts(&["a", "b"]))
+        .await;
+    assert_eq!(results.len(), 2);
+    for result in &results {
+        assert_eq!(
+            result.outcome,
+            ApplyOutcome::NotEligible(UnlockStatus::TooYoung)
+        );
+    }
+    assert_eq!(server.count(APPLY_PATH), 0);
+}
+
+#[tokio::test]
+async fn log_lines_are_labelled() {
+    let server = MockServer::start().await;
+    server.on_state(error_body(100004));
+    let sink = Arc::new(RecordingSink::default());
+
+    let results = server
+        .engine_with_events(sink.clone())
+        .submit_all(&clock(), &accounts(&["main", "spare"]))
+        .await;
+    assert!(
+        results
+            .iter()
+            .all(|r| r.outcome == ApplyOutcome::TokenExpired)
+    );
+
+    let lines = sink.lines();
+    for label in ["main", "spare"] {
+        let prefix = format!("[{}] ", label);
+        assert!(
+            lines.iter().any(|line| line.starts_with(&prefix)),
+            "нет строк с {}: {:?}",
+            prefix,
+            lines
+        );
+    }
+    assert!(lines.iter().any(|line| line == "  main: токен устарел"));
+}
+
+#[tokio::test]
+async fn log_lines_keep_levels() {
+    let server = MockServer::start().await;
+    server.on_state(error_body(100004));
+    let sink = Arc::new(RecordingSink::default());
+
+    server
+        .engine_with_events(sink.clone())
+        .submit_all(&clock(), &accounts(&["main"]))
+        .await;
+
+    let events = sink.events();
+    assert!(
+        events.contains(&(
+            Level::ERROR,
+            "[main] Cookie (токен) устарел, обновите. (code 100004)".to_string()
+        )),
+        "{:?}",
+        events
+    );
+    assert!(events.contains(&(Level::INFO, "Очередь из 1 аккаунтов".to_string())));
+}
@@ -0,0 +1,266 @@
+use std::{fs, sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use micommunity_core::{
+    ApplyOutcome, AttemptRecord, Config, Engine, FakeClock, HistoryStore, NullSink, RequestRecord,
+    ScheduleConfig, StrategyConfig,
+    adaptive::{AdaptiveStrategy, Timing},
+    network::{FormulaStrategy, SendStrategy},
+};
+use reqwest::Client;
+
+const LATENCY_MS: f64 = 100.0;
+const MAX_SHIFT: Duration = Duration::from_millis(1500);
+
+fn midnight() -> DateTime<Tz> {
+    Shanghai.with_ymd_and_hms(2025, 3, 11, 0, 0, 0).unwrap()
+}
+
+/// Попытка, отправленная на `shift_ms` позже формулы, с ответом
+/// `apply_result`, пришедшим за `reset_offset_ms` от сброса лимита.
+fn attempt(
+    shift_ms: i64,
+    apply_result: i64,
+    reset_offset_ms: Option<i64>,
+    success: bool,
+) -> AttemptRecord {
+    let target_time =
+        FormulaStrategy.target_time(midnight(), LATENCY_MS) + TimeDelta::milliseconds(shift_ms);
+    let outcome = if success {
+        ApplyOutcome::Accepted
+    } else {
+        ApplyOutcome::QuotaExhausted { retry_at: None }
+    };
+    AttemptRecord {
+        latency_ms: Some(LATENCY_MS),
+        target_time: Some(target_time.fixed_offset()),
+        requests: vec![RequestRecord {
+            index: 0,
+            scheduled: target_time.fixed_offset(),
+            sent: target_time.fixed_offset(),
+            received: None,
+            server_date: None,
+            http_status: Some(200),
+            code: Some(0),
+            apply_result: Some(apply_result),
+            reset_offset_ms,
+            error: None,
+        }],
+        decisive: Some(0),
+        ..AttemptRecord::new(&outcome)
+    }
+}
+
+fn learn(history: &[AttemptRecord]) -> AdaptiveStrategy {
+    AdaptiveStrategy::learn(Arc::new(FormulaStrategy), history, MAX_SHIFT)
+}
+
+#[test]
+fn timing_classifies_attempts() {
+    assert_eq!(
+        Timing::of(&attempt(0, 1, None, true)),
+        Some(Timing::Success)
+    );
+    assert_eq!(
+        Timing::of(&attempt(0, 4, Some(-200), false)),
+        Some(Timing::Early {
+            reset_offset_ms: -200
+        })
+    );
+    assert_eq!(
+        Timing::of(&attempt(0, 3, Some(40), false)),
+        Some(Timing::Late {
+            reset_offset_ms: Some(40)
+        })
+    );
+    assert_eq!(
+        Timing::of(&attempt(0, 3, None, false)),
+        Some(Timing::Late {
+            reset_offset_ms: None
+        })
+    );
+
+    // Ошибка API и попытка без отправки ничего не говорят о времени
+    let mut failed = attempt(0, 3, Some(40), false);
+    failed.requests[0].code = Some(100001);
+    assert_eq!(Timing::of(&failed), None);
+    assert_eq!(
+        Timing::of(&AttemptRecord::new(&ApplyOutcome::TimeSyncFailed)),
+        None
+    );
+}
+
+#[test]
+fn empty_history_keeps_base_strategy() {
+    let strategy = learn(&[]);
+    assert_eq!(strategy.shift(), TimeDelta::zero());
+    assert_eq!(
+        strategy.target_time(midnight(), LATENCY_MS),
+        FormulaStrategy.target_time(midnight(), LATENCY_MS)
+    );
+    assert!(strategy.describe().contains("истории нет"));
+}
+
+#[test]
+fn early_attempt_moves_send_later() {
+    let strategy = learn(&[attempt(0, 4, Some(-200), false)]);
+    assert_eq!(strategy.shift(), TimeDelta::milliseconds(230));
+    assert_eq!(
+        strategy.target_time(midnight(), LATENCY_MS),
+        FormulaStrategy.target_time(midnight(), LATENCY_MS) + TimeDelta::milliseconds(230)
+    );
+    let describe = strategy.describe();
+    assert!(describe.contains("+230 мс"), "{}", describe);
+    assert!(
+        describe.contains("рано, за 200 мс до сброса"),
+        "{}",
+        describe
+    );
+}
+
+#[test]
+fn late_attempt_moves_send_earlier() {
+    // Пришел через 300 мс после сброса - раньше ровно настолько, чтобы прийти
+    // через 30 мс после сброса, как и после ранней попытки
+    assert_eq!(
+        learn(&[attempt(0, 3, Some(300), false)]).shift(),
+        TimeDelta::milliseconds(-270)
+    );
+    // Небольшое или неизвестное опоздание - минимальный шаг
+    assert_eq!(
+        learn(&[attempt(0, 3, Some(20), false)]).shift(),
+        TimeDelta::milliseconds(-100)
+    );
+    assert_eq!(
+        learn(&[attempt(0, 3, None, false)]).shift(),
+        TimeDelta::milliseconds(-100)
+    );
+}
+
+#[test]
+fn shift_used_on_previous_nights_is_kept() {
+    // Прошлые ночи уже отправлялись на 230 мс позже формулы и были удачны
+    let history = [attempt(230, 1, Some(30), true), attempt(230, 1, None, true)];
+    assert_eq!(learn(&history).shift(), TimeDelta::milliseconds(230));
+
+    // Ночь на 230 мс позже все же опоздала на 130 мс - чуть раньше
+    let history = [attempt(230, 3, Some(130), false)];
+    assert_eq!(learn(&history).shift(), TimeDelta::milliseconds(130));
+}
+
+#[test]
+fn burst_learns_from_the_decisive_request() {
+    // Серия из трех запросов через 200 мс вокруг формулы: первые два получили
+    // отказ до сброса, третий, отправленный на 200 мс позже центра, пришел за
+    // 200 мс до сброса - центр нужно сдвинуть на 200 + 230 мс
+    let mut record = attempt(0, 4, Some(-200), false);
+    let center = record.requests[0].scheduled;
+    record.requests = (0..3)
+        .map(|index| {
+            let scheduled = center + TimeDelta::milliseconds(200 * (index as i64 - 1));
+            RequestRecord {
+                index,
+                scheduled,
+                sent: scheduled,
+                reset_offset_ms: Some(-200 - 200 * (2 - index as i64)),
+                ..record.requests[0].clone()
+            }
+        })
+        .collect();
+    record.decisive = Some(2);
+    assert_eq!(learn(&[record]).shift(), TimeDelta::milliseconds(430));
+}
+
+#[test]
+fn shift_is_bounded() {
+    let strategy = learn(&[attempt(0, 4, Some(-5000), false)]);
+    assert_eq!(strategy.shift(), TimeDelta::milliseconds(1500));
+    assert!(strategy.describe().contains("ограничена 1500 мс"));
+
+    let strategy = learn(&[attempt(-1400, 3, Some(900), false)]);
+    assert_eq!(strategy.shift(), TimeDelta::milliseconds(-1500));
+}
+
+#[test]
+fn only_recent_attempts_count() {
+    let mut history: Vec<AttemptRecord> =
+        (0..10).map(|_| attempt(0, 4, Some(-1000), false)).collect();
+    history.extend((0..10).map(|_| attempt(0, 1, None, true)));
+    assert_eq!(learn(&history).shift(), TimeDelta::zero());
+}
+
+#[test]
+fn adaptive_strategy_config_roundtrip() {
+    let config: StrategyConfig = "adaptive".parse().unwrap();
+    assert_eq!(
+        config,
+        StrategyConfig::Adaptive {
+            max_shift: MAX_SHIFT
+        }
+    );
+    assert_eq!(config.to_string(), "adaptive:1500");
+
+    let config: StrategyConfig = "adaptive:800".parse().unwrap();
+    assert_eq!(
+        config,
+        StrategyConfig::Adaptive {
+            max_shift: Duration::from_millis(800)
+        }
+    );
+    assert_eq!(config.to_string().parse::<StrategyConfig>(), Ok(config));
+    assert!("adaptive:soon".parse::<StrategyConfig>().is_err());
+
+    let strategy = StrategyConfig::Adaptive {
+        max_shift: Duration::from_millis(800),
+    }
+    .build_with_history(&[attempt(0, 4, Some(-5000), false)]);
+    assert_eq!(
+        strategy.target_time(midnight(), LATENCY_MS),
+        FormulaStrategy.target_time(midnight(), LATENCY_MS) + TimeDelta::milliseconds(800)
+    );
+}
+
+#[test]
+fn each_account_learns_from_its_own_history() {
+    let path =
+        std::env::temp_dir().join(format!("micommunity-adaptive-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let store = HistoryStore::new(&path);
+    let labelled = |label: &str, record: AttemptRecord| AttemptRecord {
+        account: Some(label.to_string()),
+        ..record
+    };
+    // main пришел рано, spare - поздно, запуск без названия - очень рано
+    store
+        .append(&labelled("main", attempt(0, 4, Some(-200), false)))
+        .unwrap();
+    store
+        .append(&labelled("spare", attempt(0, 3, Some(300), false)))
+        .unwrap();
+    store.append(&attempt(0, 4, Some(-1000), false)).unwrap();
+
+    let config = Config {
+        schedule: ScheduleConfig {
+            strategy: StrategyConfig::Adaptive {
+                max_shift: MAX_SHIFT,
+            },
+            ..ScheduleConfig::default()
+        },
+        ..Config::default()
+    };
+    let engine = Engine::with_config(Client::new(), Arc::new(NullSink), config).with_history(store);
+    let clock = FakeClock::new(midnight() - TimeDelta::hours(3));
+    let formula = FormulaStrategy.target_time(midnight(), LATENCY_MS);
+    let target = |engine: Engine| engine.learned().target_time(&clock, LATENCY_MS) - formula;
+
+    assert_eq!(
+        target(engine.for_account("main")),
+        TimeDelta::milliseconds(230)
+    );
+    assert_eq!(
+        target(engine.for_account("spare")),
+        TimeDelta::milliseconds(-270)
+    );
+    assert_eq!(target(engine), TimeDelta::milliseconds(1030));
+}
@@ -0,0 +1,296 @@
+// Локальные серверы, подменяющие Mi Community и NTP в тестах.
+#![allow(dead_code)]
+
+pub mod ntp;
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use micommunity_core::{
+    ApiConfig, AttemptRecord, Clock, Config, Deadline, Engine, EventSink, FakeClock, NullSink,
+};
+use reqwest::Client;
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use tracing::Level;
+
+pub const BASE_PATH: &str = "/bbs/api/global";
+pub const STATE_PATH: &str = "/bbs/api/global/user/bl-switch/state";
+pub const APPLY_PATH: &str = "/bbs/api/global/apply/bl-auth";
+
+/// Запрос, полученный сервером.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub cookie: Option<String>,
+}
+
+/// Очередь ответов эндпоинта: последний ответ повторяется.
+#[derive(Default)]
+struct Responses(VecDeque<Value>);
+
+impl Responses {
+    fn next(&mut self) -> Option<Value> {
+        if self.0.len() > 1 {
+            self.0.pop_front()
+        } else {
+            self.0.front().cloned()
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    state: Responses,
+    apply: Responses,
+    date: Option<String>,
+    delay: Duration,
+    requests: Vec<RecordedRequest>,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone()));
+            }
+        });
+        MockServer { addr, state, task }
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}{}", self.addr, BASE_PATH)
+    }
+
+    /// Добавляет ответ `user/bl-switch/state` в очередь.
+    pub fn on_state(&self, body: Value) -> &Self {
+        self.state.lock().unwrap().state.0.push_back(body);
+        self
+    }
+
+    /// Добавляет ответ `apply/bl-auth` в очередь.
+    pub fn on_apply(&self, body: Value) -> &Self {
+        self.state.lock().unwrap().apply.0.push_back(body);
+        self
+    }
+
+    /// Заголовок `Date` во всех следующих ответах.
+    pub fn on_date(&self, date: &str) -> &Self {
+        self.state.lock().unwrap().date = Some(date.to_string());
+        self
+    }
+
+    /// Задержка перед каждым следующим ответом.
+    pub fn on_delay(&self, delay: Duration) -> &Self {
+        self.state.lock().unwrap().delay = delay;
+        self
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn count(&self, path: &str) -> usize {
+        self.requests().iter().filter(|r| r.path == path).count()
+    }
+
+    /// Движок, направленный на этот сервер.
+    pub fn engine(&self) -> Engine {
+        self.engine_with_events(Arc::new(NullSink))
+    }
+
+    pub fn engine_with_events(&self, events: Arc<dyn EventSink>) -> Engine {
+        Engine::with_config(Client::new(), events, self.config())
+    }
+
+    /// Настройки по умолчанию с адресом этого сервера.
+    pub fn config(&self) -> Config {
+        Config {
+            api: ApiConfig {
+                base_url: self.base_url(),
+            },
+            ..Config::default()
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Запоминает строки лога с уровнями и записи истории движка.
+#[derive(Default)]
+pub struct RecordingSink {
+    lines: Mutex<Vec<(Level, String)>>,
+    attempts: Mutex<Vec<AttemptRecord>>,
+}
+
+impl RecordingSink {
+    pub fn lines(&self) -> Vec<String> {
+        self.events().into_iter().map(|(_, line)| line).collect()
+    }
+
+    pub fn events(&self) -> Vec<(Level, String)> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    pub fn attempts(&self) -> Vec<AttemptRecord> {
+        self.attempts.lock().unwrap().clone()
+    }
+}
+
+impl EventSink for RecordingSink {
+    fn log(&self, message: &str) {
+        self.event(Level::INFO, message);
+    }
+
+    fn event(&self, level: Level, message: &str) {
+        self.lines
+            .lock()
+            .unwrap()
+            .push((level, message.to_string()));
+    }
+
+    fn attempt(&self, record: &AttemptRecord) {
+        self.attempts.lock().unwrap().push(record.clone());
+    }
+}
+
+/// Полдень по Пекину, далеко от полуночи: время часов [`clock`].
+pub fn noon() -> DateTime<Tz> {
+    Shanghai.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap()
+}
+
+/// Часы, идущие от [`noon`].
+pub fn clock() -> Arc<dyn Clock> {
+    Arc::new(FakeClock::new(noon()))
+}
+
+/// `deadline_format`, разобранный так же, как его разбирает движок по [`clock`].
+pub fn deadline(text: &str) -> Option<Deadline> {
+    Some(Deadline::parse(text, noon().date_naive()))
+}
+
+pub fn state_body(is_pass: i64, button_state: Option<i64>, deadline: Option<&str>) -> Value {
+    json!({
+        "code": 0,
+        "msg": "success",
+        "data": {
+            "is_pass": is_pass,
+            "button_state": button_state,
+            "deadline_format": deadline,
+        }
+    })
+}
+
+pub fn apply_body(apply_result: i64, deadline: Option<&str>) -> Value {
+    json!({
+        "code": 0,
+        "msg": "success",
+        "data": {
+            "apply_result": apply_result,
+            "deadline_format": deadline,
+        }
+    })
+}
+
+pub fn error_body(code: i64) -> Value {
+    json!({ "code": code, "msg": "error", "data": {} })
+}
+
+/// Обслуживает keep-alive соединение: reqwest держит его в пуле.
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let mut buffer = Vec::new();
+    loop {
+        let header_end = loop {
+            if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let mut chunk = [0u8; 4096];
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+        };
+        let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+        let method = request_line.next().unwrap_or_default().to_string();
+        let path = request_line.next().unwrap_or_default().to_string();
+        let mut cookie = None;
+        let mut content_length = 0;
+        for line in lines {
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("cookie") {
+                    cookie = Some(value.to_string());
+                } else if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.parse().unwrap_or(0);
+                }
+            }
+        }
+        while buffer.len() < header_end + content_length {
+            let mut chunk = [0u8; 4096];
+            match stream.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+            }
+        }
+        buffer.drain(..header_end + content_length);
+
+        let (body, date, delay) = {
+            let mut state = state.lock().unwrap();
+            let body = match path.as_str() {
+                STATE_PATH => state.state.next(),
+                APPLY_PATH => state.apply.next(),
+                _ => None,
+            };
+            state.requests.push(RecordedRequest {
+                method,
+                path,
+                cookie,
+            });
+            (body, state.date.clone(), state.delay)
+        };
+        tokio::time::sleep(delay).await;
+        let date = date.map_or(String::new(), |date| format!("Date: {}\r\n", date));
+        let response = match body {
+            Some(body) => {
+                let body = body.to_string();
+                format!(
+                    "HTTP/1.1 200 OK\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    date,
+                    body.len(),
+                    body
+                )
+            }
+            None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+        };
+        if stream.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Локальный NTP-сервер (SNTP, RFC 4330) для тестов синхронизации времени.
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::TimeDelta;
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+// Секунд между 1900-01-01 (эпоха NTP) и 1970-01-01
+const NTP_EPOCH_OFFSET: u64 = 2_208_988_800;
+
+/// Как сервер отвечает на запросы.
+#[derive(Debug, Clone, Copy)]
+pub enum NtpBehavior {
+    /// Время сервера = системное время + смещение.
+    Offset(TimeDelta),
+    /// Фиксированное время сервера (Unix time).
+    Time(SystemTime),
+    /// Не отвечать (таймаут у клиента).
+    Silent,
+    /// Kiss-o'-Death: stratum 0, клиент должен отвергнуть ответ.
+    KissOfDeath,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    behavior: NtpBehavior,
+    /// Задержка туда и обратно, делится поровну между запросом и ответом.
+    delay: Duration,
+}
+
+pub struct NtpResponder {
+    addr: SocketAddr,
+    settings: Arc<Mutex<Settings>>,
+    task: JoinHandle<()>,
+}
+
+impl NtpResponder {
+    pub async fn start(behavior: NtpBehavior) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let settings = Arc::new(Mutex::new(Settings {
+            behavior,
+            delay: Duration::ZERO,
+        }));
+        let shared = settings.clone();
+        let socket = Arc::new(socket);
+        let task = tokio::spawn(async move {
+            let mut request = [0u8; 48];
+            while let Ok((len, peer)) = socket.recv_from(&mut request).await {
+                if len < 48 {
+                    continue;
+                }
+                let settings = *shared.lock().unwrap();
+                let socket = socket.clone();
+                tokio::spawn(async move {
+                    reply(&socket, peer, request, settings).await;
+                });
+            }
+        });
+        NtpResponder {
+            addr,
+            settings,
+            task,
+        }
+    }
+
+    pub async fn with_offset_ms(offset_ms: i64) -> Self {
+        NtpResponder::start(NtpBehavior::Offset(TimeDelta::milliseconds(offset_ms))).await
+    }
+
+    /// `хост:порт` для `NtpConfig::servers`.
+    pub fn server(&self) -> String {
+        self.addr.to_string()
+    }
+
+    pub fn set_behavior(&self, behavior: NtpBehavior) {
+        self.settings.lock().unwrap().behavior = behavior;
+    }
+
+    pub fn set_delay(&self, delay: Duration) -> &Self {
+        self.settings.lock().unwrap().delay = delay;
+        self
+    }
+}
+
+impl Drop for NtpResponder {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn server_time(behavior: NtpBehavior) -> SystemTime {
+    match behavior {
+        NtpBehavior::Offset(offset) => {
+            let now = SystemTime::now();
+            match offset.to_std() {
+                Ok(ahead) => now + ahead,
+                Err(_) => now - (-offset).to_std().unwrap(),
+            }
+        }
+        NtpBehavior::Time(time) => time,
+        NtpBehavior::Silent | NtpBehavior::KissOfDeath => SystemTime::now(),
+    }
+}
+
+fn ntp_timestamp(time: SystemTime) -> [u8; 8] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap();
+    let seconds = (since_epoch.as_secs() + NTP_EPOCH_OFFSET) as u32;
+    let fraction = ((since_epoch.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    bytes[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+    bytes
+}
+
+async fn reply(socket: &UdpSocket, peer: SocketAddr, request: [u8; 48], settings: Settings) {
+    if matches!(settings.behavior, NtpBehavior::Silent) {
+        return;
+    }
+    tokio::time::sleep(settings.delay / 2).await;
+    let received = ntp_timestamp(server_time(settings.behavior));
+
+    let mut response = [0u8; 48];
+    // LI 0, версия как у клиента, режим 4 (сервер)
+    response[0] = (request[0] & 0b0011_1000) | 4;
+    response[1] = match settings.behavior {
+        NtpBehavior::KissOfDeath => 0,
+        _ => 1,
+    };
+    response[2] = request[2];
+    response[3] = 0xEC; // точность 2^-20 с
+    response[12..16].copy_from_slice(match settings.behavior {
+        NtpBehavior::KissOfDeath => b"DENY",
+        _ => b"LOCL",
+    });
+    response[16..24].copy_from_slice(&received);
+    // Originate = transmit клиента, по нему клиент сопоставляет ответ
+    response[24..32].copy_from_slice(&request[40..48]);
+    response[32..40].copy_from_slice(&received);
+    response[40..48].copy_from_slice(&ntp_timestamp(server_time(settings.behavior)));
+
+    tokio::time::sleep(settings.delay / 2).await;
+    let _ = socket.send_to(&response, peer).await;
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use micommunity_core::{
+    ApiConfig, ApplyOutcome, Clock, Config, Deadline, Engine, FakeClock, NullSink, RetryConfig,
+    UnlockStatus, daemon::next_attempt_midnight,
+};
+use reqwest::Client;
+
+use common::{APPLY_PATH, MockServer, STATE_PATH, deadline, state_body};
+
+fn beijing(date: (i32, u32, u32), time: (u32, u32, u32)) -> DateTime<Tz> {
+    Shanghai
+        .with_ymd_and_hms(date.0, date.1, date.2, time.0, time.1, time.2)
+        .unwrap()
+}
+
+fn engine(server: &MockServer, max_days: u32) -> Engine {
+    let config = Config {
+        api: ApiConfig {
+            base_url: server.base_url(),
+        },
+        retry: RetryConfig {
+            max_days,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+    Engine::with_config(Client::new(), Arc::new(NullSink), config)
+}
+
+#[test]
+fn stops_when_approved_or_token_expired() {
+    let now = beijing((2025, 3, 10), (0, 0, 1));
+    for outcome in [
+        ApplyOutcome::Approved { deadline: None },
+        ApplyOutcome::Accepted,
+        ApplyOutcome::TokenExpired,
+        ApplyOutcome::NotEligible(UnlockStatus::TokenExpired),
+    ] {
+        assert_eq!(next_attempt_midnight(&outcome, now), None, "{:?}", outcome);
+    }
+}
+
+#[test]
+fn retries_next_midnight() {
+    let now = beijing((2025, 3, 10), (0, 0, 1));
+    let next = beijing((2025, 3, 11), (0, 0, 0));
+    for outcome in [
+        ApplyOutcome::TooLate {
+            blocked_until: None,
+        },
+        ApplyOutcome::RequestRejected,
+        ApplyOutcome::TimeSyncFailed,
+        ApplyOutcome::NotEligible(UnlockStatus::TooYoung),
+    ] {
+        assert_eq!(
+            next_attempt_midnight(&outcome, now),
+            Some(next),
+            "{:?}",
+            outcome
+        );
+    }
+}
+
+#[test]
+fn waits_for_block_to_expire() {
+    let now = beijing((2025, 3, 10), (0, 0, 1));
+    let outcome = ApplyOutcome::QuotaExhausted {
+        retry_at: Some(Deadline::parse("03/14", now.date_naive())),
+    };
+    assert_eq!(
+        next_attempt_midnight(&outcome, now),
+        Some(beijing((2025, 3, 14), (0, 0, 0)))
+    );
+
+    let blocked = ApplyOutcome::NotEligible(UnlockStatus::Blocked {
+        until: Some(Deadline::parse("3/20", now.date_naive())),
+    });
+    assert_eq!(
+        next_attempt_midnight(&blocked, now),
+        Some(beijing((2025, 3, 20), (0, 0, 0)))
+    );
+}
+
+#[test]
+fn block_date_rolls_over_new_year() {
+    let now = beijing((2025, 12, 30), (0, 0, 1));
+    let outcome = ApplyOutcome::TooLate {
+        blocked_until: Some(Deadline::parse("01/02", now.date_naive())),
+    };
+    assert_eq!(
+        next_attempt_midnight(&outcome, now),
+        Some(beijing((2026, 1, 2), (0, 0, 0)))
+    );
+}
+
+#[test]
+fn expired_or_unparsable_block_means_next_midnight() {
+    let now = beijing((2026, 1, 3), (0, 0, 1));
+    let next = beijing((2026, 1, 4), (0, 0, 0));
+    for date in ["12/31", "01/03", "Не указано"] {
+        let outcome = ApplyOutcome::QuotaExhausted {
+            retry_at: Some(Deadline::parse(date, now.date_naive())),
+        };
+        assert_eq!(next_attempt_midnight(&outcome, now), Some(next), "{}", date);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn daemon_stops_when_approved() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(1, None, Some("11/01")));
+    let clock = FakeClock::new(beijing((2025, 3, 10), (12, 0, 0)));
+
+    let outcome = engine(&server, 30)
+        .run_daemon(&clock, "token", "device")
+        .await;
+    assert_eq!(
+        outcome,
+        ApplyOutcome::Approved {
+            deadline: deadline("11/01")
+        }
+    );
+    assert_eq!(server.count(STATE_PATH), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn daemon_checks_once_per_night_until_limit() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(4, Some(3), None));
+    let clock = FakeClock::new(beijing((2025, 3, 10), (12, 0, 0)));
+
+    let outcome = engine(&server, 3)
+        .run_daemon(&clock, "token", "device")
+        .await;
+    assert_eq!(outcome, ApplyOutcome::NotEligible(UnlockStatus::TooYoung));
+    // Сразу при запуске и перед каждой из трех полуночей
+    assert_eq!(server.count(STATE_PATH), 4);
+    assert_eq!(server.count(APPLY_PATH), 0);
+    let now = clock.now();
+    assert!(now >= beijing((2025, 3, 12), (23, 55, 0)), "{}", now);
+    assert!(now < beijing((2025, 3, 13), (0, 0, 0)), "{}", now);
+}
@@ -0,0 +1,82 @@
+use chrono::{NaiveDate, TimeDelta, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use micommunity_core::{
+    ApplyOutcome, Deadline, UnlockStatus,
+    deadline::{format_remaining, parse_month_day},
+};
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+#[test]
+fn parses_month_day_in_current_year() {
+    let today = date(2025, 3, 10);
+    assert_eq!(parse_month_day("03/14", today), Some(date(2025, 3, 14)));
+    assert_eq!(parse_month_day(" 3/9 ", today), Some(date(2025, 3, 9)));
+}
+
+#[test]
+fn parses_month_day_across_new_year() {
+    assert_eq!(
+        parse_month_day("01/02", date(2025, 12, 30)),
+        Some(date(2026, 1, 2))
+    );
+    assert_eq!(
+        parse_month_day("12/31", date(2026, 1, 3)),
+        Some(date(2025, 12, 31))
+    );
+}
+
+#[test]
+fn prefers_future_date_beyond_a_few_days_back() {
+    let today = date(2025, 3, 10);
+    // Больше полугода вперед - все равно этот год, а не прошлый
+    assert_eq!(parse_month_day("09/20", today), Some(date(2025, 9, 20)));
+    // До трех дней назад - расхождение часов, дальше - следующий год
+    assert_eq!(parse_month_day("03/07", today), Some(date(2025, 3, 7)));
+    assert_eq!(parse_month_day("03/06", today), Some(date(2026, 3, 6)));
+    assert_eq!(parse_month_day("02/29", today), Some(date(2028, 2, 29)));
+}
+
+#[test]
+fn keeps_text_when_unparsable() {
+    let deadline = Deadline::parse("Не указано", date(2025, 3, 10));
+    assert_eq!(deadline.date, None);
+    assert_eq!(deadline.midnight(), None);
+    assert_eq!(deadline.to_string(), "Не указано");
+    assert_eq!(parse_month_day("02/30", date(2025, 3, 10)), None);
+    assert_eq!(Deadline::from_api(Some(""), date(2025, 3, 10)), None);
+}
+
+#[test]
+fn midnight_and_remaining_in_beijing_time() {
+    let deadline = Deadline::parse("10/25", date(2025, 10, 20));
+    assert_eq!(deadline.to_string(), "2025-10-25");
+    let midnight = Shanghai.with_ymd_and_hms(2025, 10, 25, 0, 0, 0).unwrap();
+    assert_eq!(deadline.midnight(), Some(midnight));
+
+    let now = Shanghai.with_ymd_and_hms(2025, 10, 22, 20, 47, 15).unwrap();
+    let remaining = deadline.remaining(now).unwrap();
+    assert_eq!(
+        remaining,
+        TimeDelta::seconds(2 * 86_400 + 3 * 3600 + 12 * 60 + 45)
+    );
+    assert_eq!(format_remaining(remaining), "2 д 03:12:45");
+    assert_eq!(format_remaining(TimeDelta::seconds(61)), "00:01:01");
+    assert_eq!(format_remaining(TimeDelta::seconds(-5)), "00:00:00");
+}
+
+#[test]
+fn outcomes_expose_deadline() {
+    let deadline = Deadline::parse("11/01", date(2025, 10, 20));
+    let blocked = ApplyOutcome::NotEligible(UnlockStatus::Blocked {
+        until: Some(deadline.clone()),
+    });
+    assert_eq!(blocked.deadline(), Some(&deadline));
+    assert_eq!(
+        blocked.to_string(),
+        "не подано: заблокировано до 2025-11-01"
+    );
+    assert_eq!(ApplyOutcome::Accepted.deadline(), None);
+}
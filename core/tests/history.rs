@@ -0,0 +1,161 @@
+mod common;
+
+use std::{fs, path::PathBuf, sync::Arc};
+
+use chrono::TimeZone;
+use chrono_tz::Asia::Shanghai;
+use micommunity_core::{
+    Account, ApplyOutcome, AttemptRecord, Clock, FakeClock, HistoryStore, UnlockStatus,
+};
+
+use common::{MockServer, RecordingSink, apply_body, clock, error_body, state_body};
+
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("micommunity-history-{}", std::process::id()));
+    let path = dir.join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn store_appends_and_loads_records() {
+    let store = HistoryStore::new(temp_path("roundtrip.jsonl"));
+    assert_eq!(store.load().unwrap(), vec![]);
+
+    let first = AttemptRecord::new(&ApplyOutcome::Accepted);
+    let second = AttemptRecord {
+        account: Some("spare".to_string()),
+        latency_ms: Some(142.5),
+        ..AttemptRecord::new(&ApplyOutcome::NotEligible(UnlockStatus::TooYoung))
+    };
+    store.append(&first).unwrap();
+    store.append(&second).unwrap();
+
+    let records = store.load().unwrap();
+    assert_eq!(records, vec![first, second]);
+    assert!(records[0].success);
+    assert!(!records[1].success);
+}
+
+#[test]
+fn store_skips_damaged_lines() {
+    let store = HistoryStore::new(temp_path("damaged.jsonl"));
+    let record = AttemptRecord::new(&ApplyOutcome::TimeSyncFailed);
+    store.append(&record).unwrap();
+    let mut text = fs::read_to_string(store.path()).unwrap();
+    text.push_str("{не json\n");
+    fs::write(store.path(), text).unwrap();
+    store.append(&record).unwrap();
+
+    assert_eq!(store.load().unwrap(), vec![record.clone(), record]);
+}
+
+#[tokio::test]
+async fn apply_records_timing_telemetry() {
+    let server = MockServer::start().await;
+    server
+        .on_date("Wed, 01 Jan 2025 02:00:00 GMT")
+        .on_apply(apply_body(3, Some("01/02")));
+    let sink = Arc::new(RecordingSink::default());
+    let engine = server.engine_with_events(sink.clone());
+    let morning = Shanghai.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).unwrap();
+    let clock: Arc<dyn Clock> = Arc::new(FakeClock::new(morning));
+
+    let outcome = engine
+        .apply(&clock, morning, 87.0, "token", "device", None)
+        .await;
+    assert!(matches!(outcome, ApplyOutcome::QuotaExhausted { .. }));
+
+    let attempts = sink.attempts();
+    assert_eq!(attempts.len(), 1);
+    let record = &attempts[0];
+    assert_eq!(record.account, None);
+    assert_eq!(record.latency_ms, Some(87.0));
+    assert_eq!(record.target_time, Some(morning.fixed_offset()));
+    assert_eq!(record.ntp_offset_ms, None);
+    assert_eq!(record.outcome, outcome.to_string());
+    assert!(!record.success);
+
+    let request = record.decisive_request().unwrap();
+    assert_eq!(request.http_status, Some(200));
+    assert_eq!(request.code, Some(0));
+    assert_eq!(request.apply_result, Some(3));
+    assert!(request.received.unwrap() >= request.sent);
+    assert_eq!(request.server_date, Some(morning.fixed_offset()));
+    // Запрос дошел в 10:00:00 по заголовку Date - через 10 часов после сброса
+    let reset_offset_ms = request.reset_offset_ms.unwrap();
+    assert!((36_000_000..36_001_000).contains(&reset_offset_ms));
+}
+
+#[tokio::test]
+async fn queue_records_every_account() {
+    let server = MockServer::start().await;
+    server.on_state(error_body(100004));
+    let sink = Arc::new(RecordingSink::default());
+    let accounts: Vec<Account> = ["main", "spare"]
+        .iter()
+        .map(|label| Account {
+            label: label.to_string(),
+            cookie_value: "token".to_string(),
+            device_id: "device".to_string(),
+        })
+        .collect();
+
+    server
+        .engine_with_events(sink.clone())
+        .submit_all(&clock(), &accounts)
+        .await;
+    let mut labels: Vec<Option<String>> = sink
+        .attempts()
+        .into_iter()
+        .map(|record| record.account)
+        .collect();
+    labels.sort();
+    assert_eq!(
+        labels,
+        vec![Some("main".to_string()), Some("spare".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn ineligible_submit_is_recorded() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(4, Some(3), None));
+    let sink = Arc::new(RecordingSink::default());
+
+    server
+        .engine_with_events(sink.clone())
+        .submit(clock().as_ref(), "token", "device")
+        .await;
+    let attempts = sink.attempts();
+    assert_eq!(attempts.len(), 1);
+    assert!(attempts[0].requests.is_empty());
+    assert_eq!(attempts[0].latency_ms, None);
+}
+
+#[tokio::test]
+async fn single_run_is_found_by_account() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(4, Some(3), None));
+    let sink = Arc::new(RecordingSink::default());
+    server
+        .engine_with_events(sink.clone())
+        .with_account("main")
+        .submit(clock().as_ref(), "token", "device")
+        .await;
+
+    // Как `history --account main`: запись одиночного запуска среди прочих
+    let store = HistoryStore::new(temp_path("single.jsonl"));
+    store
+        .append(&AttemptRecord::new(&ApplyOutcome::TimeSyncFailed))
+        .unwrap();
+    for record in sink.attempts() {
+        store.append(&record).unwrap();
+    }
+    let found = store.recent(Some("main"), 0).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].account.as_deref(), Some("main"));
+    assert!(store.recent(Some("spare"), 0).unwrap().is_empty());
+    assert_eq!(store.recent(None, 0).unwrap().len(), 2);
+    assert_eq!(store.recent(None, 1).unwrap(), found);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{MockServer, RecordingSink, STATE_PATH, apply_body, clock, state_body};
+use micommunity_core::{
+    BurstConfig, Config, Engine,
+    latency::{LatencyEstimate, median},
+    network::connection_local_addr,
+};
+use reqwest::Client;
+use tracing::Level;
+
+#[test]
+fn median_of_measurements() {
+    assert_eq!(median(vec![]), None);
+    assert_eq!(median(vec![42.0]), Some(42.0));
+    assert_eq!(median(vec![30.0, 10.0, 20.0]), Some(20.0));
+    assert_eq!(median(vec![40.0, 10.0, 30.0, 20.0]), Some(25.0));
+    // Один выброс не сдвигает оценку
+    assert_eq!(median(vec![12.0, 900.0, 11.0]), Some(12.0));
+}
+
+#[test]
+fn effective_latency_prefers_request_time() {
+    let estimate = LatencyEstimate {
+        tcp_connect_ms: Some(20.0),
+        tls_handshake_ms: Some(35.0),
+        request_ms: Some(48.0),
+    };
+    assert_eq!(estimate.effective_ms(), Some(48.0));
+
+    // Без HTTPS-запроса - время TCP-соединения, рукопожатие не учитывается
+    let estimate = LatencyEstimate {
+        request_ms: None,
+        ..estimate
+    };
+    assert_eq!(estimate.effective_ms(), Some(20.0));
+
+    assert_eq!(LatencyEstimate::default().effective_ms(), None);
+}
+
+#[tokio::test]
+async fn estimate_splits_connection_and_request() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(0, Some(1), None));
+
+    let estimate = server.engine().estimate_latency().await;
+    assert!(estimate.tcp_connect_ms.is_some_and(|ms| ms >= 0.0));
+    // Сервер без TLS: рукопожатия нет
+    assert_eq!(estimate.tls_handshake_ms, None);
+    assert!(estimate.request_ms.is_some_and(|ms| ms >= 0.0));
+    assert_eq!(estimate.effective_ms(), estimate.request_ms);
+    // Прогревочный запрос и три замера
+    assert_eq!(server.count(STATE_PATH), 4);
+}
+
+#[tokio::test]
+async fn estimate_without_server_is_empty() {
+    let server = MockServer::start().await;
+    let engine = server.engine();
+    drop(server);
+
+    let estimate = engine.estimate_latency().await;
+    assert_eq!(estimate.tcp_connect_ms, None);
+    assert_eq!(estimate.request_ms, None);
+    assert_eq!(estimate.effective_ms(), None);
+}
+
+#[tokio::test]
+async fn pooled_connection_is_reused() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(0, Some(1), None));
+    let url = format!("{}/user/bl-switch/state", server.base_url());
+
+    let client = Client::new();
+    let first = client.get(&url).send().await.unwrap();
+    let warm_addr = connection_local_addr(&first);
+    assert!(warm_addr.is_some());
+    first.bytes().await.unwrap();
+    let second = client.get(&url).send().await.unwrap();
+    assert_eq!(connection_local_addr(&second), warm_addr);
+
+    // Другой клиент открывает свое соединение
+    let other = Client::new().get(&url).send().await.unwrap();
+    assert_ne!(connection_local_addr(&other), warm_addr);
+
+    let events = Arc::new(RecordingSink::default());
+    let engine = server.engine_with_events(events.clone());
+    engine.log_connection_reuse(warm_addr, &second);
+    engine.log_connection_reuse(warm_addr, &other);
+    engine.log_connection_reuse(None, &other);
+    let events = events.events();
+    assert_eq!(events.len(), 2, "{:?}", events);
+    assert_eq!(events[0].0, Level::DEBUG);
+    assert!(events[0].1.contains("по прогретому соединению"));
+    assert_eq!(events[1].0, Level::WARN);
+    assert!(events[1].1.contains("не переиспользовано"));
+}
+
+#[tokio::test]
+async fn burst_checks_reuse_only_for_first_request() {
+    let server = MockServer::start().await;
+    server.on_apply(apply_body(2, None));
+    let events = Arc::new(RecordingSink::default());
+    let config = Config {
+        burst: BurstConfig {
+            count: 3,
+            interval: Duration::from_millis(50),
+        },
+        ..server.config()
+    };
+    let engine = Engine::with_config(Client::new(), events.clone(), config);
+    let clock = clock();
+    // Ни один запрос не уходит по этому адресу, но предупреждение - одно
+    let warm_addr = "127.0.0.1:1".parse().ok();
+    let attempts = engine
+        .send_burst(&clock, clock.now(), "token", "device", warm_addr)
+        .await;
+    assert_eq!(attempts.len(), 3);
+    let warnings = events
+        .events()
+        .into_iter()
+        .filter(|(level, line)| *level == Level::WARN && line.contains("не переиспользовано"))
+        .count();
+    assert_eq!(warnings, 1);
+}
@@ -0,0 +1,467 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use micommunity_core::{
+    ApplyOutcome, BurstConfig, Clock, Config, Deadline, Engine, FakeClock, NullSink, UnlockStatus,
+    burst::{BurstAttempt, decisive_attempt},
+};
+
+use common::{
+    APPLY_PATH, MockServer, STATE_PATH, apply_body, clock, deadline, error_body, noon, state_body,
+};
+
+const TOKEN: &str = "token";
+const DEVICE_ID: &str = "device";
+
+/// Отправляет одну заявку прямо сейчас и разбирает итог так же, как `submit`.
+/// Часы стоят на полудне, далеко от полуночи.
+async fn apply_once(server: &MockServer) -> ApplyOutcome {
+    apply_at(server, noon()).await.0
+}
+
+/// Как `apply_once`, но часы стоят на `now`. Возвращает и решающую попытку.
+async fn apply_at(server: &MockServer, now: DateTime<Tz>) -> (ApplyOutcome, BurstAttempt) {
+    let engine = server.engine();
+    let clock: Arc<dyn Clock> = Arc::new(FakeClock::new(now));
+    let attempts = engine
+        .send_burst(&clock, clock.now(), TOKEN, DEVICE_ID, None)
+        .await;
+    let attempt = decisive_attempt(&attempts).expect("нет ответа от сервера");
+    let outcome = engine
+        .resolve_outcome(clock.as_ref(), attempt, TOKEN, DEVICE_ID)
+        .await;
+    (outcome, attempt.clone())
+}
+
+/// Движок, отправляющий серию из `count` запросов с интервалом `interval`.
+fn burst_engine(server: &MockServer, count: usize, interval: Duration) -> Engine {
+    let config = Config {
+        burst: BurstConfig { count, interval },
+        ..server.config()
+    };
+    Engine::with_config(reqwest::Client::new(), Arc::new(NullSink), config)
+}
+
+/// Отправляет серию из `count` запросов с интервалом `interval`, первый - сразу
+/// после сброса лимита, так что отказы в ответ окончательные.
+async fn burst(server: &MockServer, count: usize, interval: Duration) -> Vec<BurstAttempt> {
+    let engine = burst_engine(server, count, interval);
+    let clock: Arc<dyn Clock> = Arc::new(FakeClock::new(after_midnight(100)));
+    let center = TimeDelta::from_std(interval).unwrap() * (count as i32 - 1) / 2;
+    engine
+        .send_burst(&clock, clock.now() + center, TOKEN, DEVICE_ID, None)
+        .await
+}
+
+fn before_midnight(millis: i64) -> DateTime<Tz> {
+    Shanghai.with_ymd_and_hms(2025, 1, 2, 0, 0, 0).unwrap() - TimeDelta::milliseconds(millis)
+}
+
+fn after_midnight(millis: i64) -> DateTime<Tz> {
+    before_midnight(-millis)
+}
+
+async fn status_for(body: serde_json::Value) -> UnlockStatus {
+    let server = MockServer::start().await;
+    server.on_state(body);
+    server
+        .engine()
+        .check_unlock_status(clock().as_ref(), TOKEN, DEVICE_ID)
+        .await
+}
+
+#[tokio::test]
+async fn status_can_apply() {
+    assert_eq!(
+        status_for(state_body(4, Some(1), None)).await,
+        UnlockStatus::CanApply
+    );
+}
+
+#[tokio::test]
+async fn status_blocked() {
+    assert_eq!(
+        status_for(state_body(4, Some(2), Some("10/25"))).await,
+        UnlockStatus::Blocked {
+            until: deadline("10/25")
+        }
+    );
+}
+
+#[tokio::test]
+async fn status_too_young() {
+    assert_eq!(
+        status_for(state_body(4, Some(3), None)).await,
+        UnlockStatus::TooYoung
+    );
+}
+
+#[tokio::test]
+async fn status_approved() {
+    assert_eq!(
+        status_for(state_body(1, None, Some("11/01"))).await,
+        UnlockStatus::Approved {
+            deadline: deadline("11/01")
+        }
+    );
+}
+
+#[tokio::test]
+async fn status_unknown_state() {
+    assert_eq!(
+        status_for(state_body(4, Some(9), None)).await,
+        UnlockStatus::UnknownState {
+            is_pass: 4,
+            button_state: Some(9)
+        }
+    );
+}
+
+#[tokio::test]
+async fn status_token_expired() {
+    assert_eq!(
+        status_for(error_body(100004)).await,
+        UnlockStatus::TokenExpired
+    );
+}
+
+#[tokio::test]
+async fn status_unknown_code() {
+    assert_eq!(
+        status_for(error_body(100001)).await,
+        UnlockStatus::UnknownCode(100001)
+    );
+}
+
+#[tokio::test]
+async fn status_http_error() {
+    // Без ответов в очереди сервер отвечает 404
+    let server = MockServer::start().await;
+    let status = server
+        .engine()
+        .check_unlock_status(clock().as_ref(), TOKEN, DEVICE_ID)
+        .await;
+    assert!(matches!(status, UnlockStatus::RequestFailed(_)));
+}
+
+#[tokio::test]
+async fn requests_carry_token_and_device_id() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(4, Some(3), None));
+    server
+        .engine()
+        .check_unlock_status(clock().as_ref(), TOKEN, DEVICE_ID)
+        .await;
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "GET");
+    let cookie = requests[0].cookie.clone().unwrap();
+    assert!(cookie.contains("new_bbs_serviceToken=token;"));
+    assert!(cookie.contains("deviceId=device;"));
+}
+
+#[tokio::test]
+async fn submit_stops_when_not_eligible() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(4, Some(2), Some("10/25")));
+    let outcome = server
+        .engine()
+        .submit(clock().as_ref(), TOKEN, DEVICE_ID)
+        .await;
+    assert_eq!(
+        outcome,
+        ApplyOutcome::NotEligible(UnlockStatus::Blocked {
+            until: deadline("10/25")
+        })
+    );
+    assert_eq!(server.count(APPLY_PATH), 0);
+}
+
+#[tokio::test]
+async fn submit_skips_already_approved() {
+    let server = MockServer::start().await;
+    server.on_state(state_body(1, None, Some("11/01")));
+    let outcome = server
+        .engine()
+        .submit(clock().as_ref(), TOKEN, DEVICE_ID)
+        .await;
+    assert_eq!(
+        outcome,
+        ApplyOutcome::Approved {
+            deadline: deadline("11/01")
+        }
+    );
+    assert_eq!(server.count(APPLY_PATH), 0);
+}
+
+#[tokio::test]
+async fn submit_reports_expired_token() {
+    let server = MockServer::start().await;
+    server.on_state(error_body(100004));
+    let outcome = server
+        .engine()
+        .submit(clock().as_ref(), TOKEN, DEVICE_ID)
+        .await;
+    assert_eq!(outcome, ApplyOutcome::TokenExpired);
+}
+
+#[tokio::test]
+async fn apply_approved_rechecks_status() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(apply_body(1, None))
+        .on_state(state_body(1, None, Some("11/01")));
+    assert_eq!(
+        apply_once(&server).await,
+        ApplyOutcome::Approved {
+            deadline: deadline("11/01")
+        }
+    );
+    assert_eq!(server.count(APPLY_PATH), 1);
+    assert_eq!(server.count(STATE_PATH), 1);
+}
+
+#[tokio::test]
+async fn apply_quota_exhausted() {
+    let server = MockServer::start().await;
+    server.on_apply(apply_body(3, Some("10/26")));
+    assert_eq!(
+        apply_once(&server).await,
+        ApplyOutcome::QuotaExhausted {
+            retry_at: Some(Deadline::parse("10/26", noon().date_naive()))
+        }
+    );
+}
+
+#[tokio::test]
+async fn apply_blocked_far_from_midnight_is_too_late() {
+    let server = MockServer::start().await;
+    server.on_apply(apply_body(4, Some("11/15")));
+    assert_eq!(
+        apply_once(&server).await,
+        ApplyOutcome::TooLate {
+            blocked_until: Some(Deadline::parse("11/15", noon().date_naive()))
+        }
+    );
+}
+
+#[tokio::test]
+async fn apply_blocked_in_time_is_accepted() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(apply_body(4, Some("01/03")))
+        .on_state(state_body(4, Some(1), None));
+    let (outcome, attempt) = apply_at(&server, before_midnight(500)).await;
+    assert_eq!(outcome, ApplyOutcome::Accepted);
+    let offset = attempt.reset_offset().unwrap();
+    assert!(offset < TimeDelta::zero() && offset > TimeDelta::milliseconds(-500));
+    assert_eq!(server.count(STATE_PATH), 1);
+}
+
+#[tokio::test]
+async fn apply_blocked_confirmed_by_status() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(apply_body(4, Some("01/03")))
+        .on_state(state_body(4, Some(2), Some("01/05")));
+    let (outcome, _) = apply_at(&server, before_midnight(500)).await;
+    assert_eq!(
+        outcome,
+        ApplyOutcome::TooLate {
+            blocked_until: deadline("01/05")
+        }
+    );
+}
+
+#[tokio::test]
+async fn apply_blocked_long_after_midnight_is_too_late() {
+    // Утром статус не проверился: время получения далеко от сброса лимита
+    let server = MockServer::start().await;
+    server
+        .on_apply(apply_body(4, Some("01/03")))
+        .on_state(error_body(100001));
+    let morning = Shanghai.with_ymd_and_hms(2025, 1, 2, 11, 0, 0).unwrap();
+    let (outcome, attempt) = apply_at(&server, morning).await;
+    assert!(attempt.reset_offset().unwrap() > TimeDelta::hours(10));
+    assert_eq!(
+        outcome,
+        ApplyOutcome::TooLate {
+            blocked_until: Some(Deadline::parse("01/03", morning.date_naive()))
+        }
+    );
+    assert_eq!(server.count(STATE_PATH), 1);
+}
+
+#[tokio::test]
+async fn apply_blocked_but_status_approved() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(apply_body(4, Some("11/15")))
+        .on_state(state_body(1, None, Some("11/01")));
+    assert_eq!(
+        apply_once(&server).await,
+        ApplyOutcome::Approved {
+            deadline: deadline("11/01")
+        }
+    );
+}
+
+#[tokio::test]
+async fn server_date_corrects_arrival_estimate() {
+    // Наши часы говорят 23:59:55, а сервер ответил в 00:00:02 по Пекину
+    let server = MockServer::start().await;
+    server
+        .on_date("Wed, 01 Jan 2025 16:00:02 GMT")
+        .on_apply(apply_body(4, Some("01/03")))
+        .on_state(state_body(4, Some(1), None));
+    let (outcome, attempt) = apply_at(&server, before_midnight(5000)).await;
+    assert_eq!(
+        attempt.server_date,
+        Some(Shanghai.with_ymd_and_hms(2025, 1, 2, 0, 0, 2).unwrap())
+    );
+    assert_eq!(attempt.reset_offset(), Some(TimeDelta::seconds(2)));
+    assert_eq!(outcome, ApplyOutcome::Accepted);
+}
+
+#[tokio::test]
+async fn apply_unknown_result() {
+    let server = MockServer::start().await;
+    server.on_apply(apply_body(7, None));
+    assert_eq!(
+        apply_once(&server).await,
+        ApplyOutcome::UnknownApplyResult(7)
+    );
+}
+
+#[tokio::test]
+async fn apply_request_rejected() {
+    let server = MockServer::start().await;
+    server.on_apply(error_body(100001));
+    assert_eq!(apply_once(&server).await, ApplyOutcome::RequestRejected);
+}
+
+#[tokio::test]
+async fn apply_possibly_approved_confirmed_by_status() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(error_body(100003))
+        .on_state(state_body(1, None, Some("11/01")));
+    assert_eq!(
+        apply_once(&server).await,
+        ApplyOutcome::Approved {
+            deadline: deadline("11/01")
+        }
+    );
+}
+
+#[tokio::test]
+async fn apply_possibly_approved_not_confirmed() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(error_body(100003))
+        .on_state(state_body(4, Some(1), None));
+    assert_eq!(apply_once(&server).await, ApplyOutcome::UnknownCode(100003));
+}
+
+#[tokio::test]
+async fn apply_token_expired() {
+    let server = MockServer::start().await;
+    server.on_apply(error_body(100004));
+    assert_eq!(apply_once(&server).await, ApplyOutcome::TokenExpired);
+}
+
+#[tokio::test]
+async fn burst_sends_every_request() {
+    let server = MockServer::start().await;
+    server.on_apply(error_body(100003));
+    let attempts = burst(&server, 3, Duration::from_millis(100)).await;
+    assert_eq!(server.count(APPLY_PATH), 3);
+    let indices: Vec<usize> = attempts.iter().map(|a| a.index).collect();
+    assert_eq!(indices, [0, 1, 2]);
+    assert!(attempts.iter().all(|a| a.code == Some(100003)));
+    assert!(attempts[0].sent < attempts[1].sent && attempts[1].sent < attempts[2].sent);
+}
+
+#[tokio::test]
+async fn burst_stops_after_final_answer() {
+    let server = MockServer::start().await;
+    server
+        .on_apply(error_body(100003))
+        .on_apply(apply_body(3, Some("10/26")));
+    let attempts = burst(&server, 4, Duration::from_millis(300)).await;
+    // Третий и четвертый запросы отменены, не дойдя до сервера
+    assert_eq!(server.count(APPLY_PATH), 2);
+    assert_eq!(attempts.len(), 2);
+    assert!(attempts[1].is_final());
+    assert_eq!(decisive_attempt(&attempts).map(|a| a.index), Some(1));
+}
+
+#[tokio::test]
+async fn burst_stops_after_expired_token() {
+    let server = MockServer::start().await;
+    server.on_apply(error_body(100004));
+    let attempts = burst(&server, 3, Duration::from_millis(300)).await;
+    // С устаревшим токеном остальные запросы серии не отправляются
+    assert_eq!(server.count(APPLY_PATH), 1);
+    assert_eq!(attempts.len(), 1);
+    assert!(attempts[0].is_final());
+    assert_eq!(
+        decisive_attempt(&attempts).and_then(|a| a.code),
+        Some(100004)
+    );
+}
+
+#[tokio::test]
+async fn burst_waits_for_sent_requests_after_final_answer() {
+    let server = MockServer::start().await;
+    server
+        .on_delay(Duration::from_millis(600))
+        .on_apply(apply_body(3, Some("10/26")))
+        .on_apply(apply_body(1, Some("10/30")));
+    let attempts = burst(&server, 3, Duration::from_millis(400)).await;
+    // Второй запрос отправлен до ответа на первый, третий - уже нет
+    assert_eq!(server.count(APPLY_PATH), 2);
+    assert_eq!(attempts.len(), 2);
+    assert!(attempts.iter().all(|a| a.received.is_some()));
+    let decisive = decisive_attempt(&attempts).unwrap();
+    assert_eq!(decisive.index, 1);
+    assert_eq!(decisive.apply_result, Some(1));
+}
+
+#[tokio::test]
+async fn burst_continues_past_refusals_before_reset() {
+    // Первые два запроса доходят до сброса лимита, третий - через 300 мс после
+    let server = MockServer::start().await;
+    server
+        .on_apply(apply_body(3, Some("01/03")))
+        .on_apply(apply_body(4, Some("01/03")))
+        .on_state(state_body(4, Some(2), Some("01/05")));
+    let engine = burst_engine(&server, 3, Duration::from_millis(400));
+    let clock: Arc<dyn Clock> = Arc::new(FakeClock::new(before_midnight(500)));
+    let target = clock.now() + TimeDelta::milliseconds(400);
+    let attempts = engine
+        .send_burst(&clock, target, TOKEN, DEVICE_ID, None)
+        .await;
+    assert_eq!(server.count(APPLY_PATH), 3);
+    assert_eq!(attempts.len(), 3);
+    assert!(!attempts[0].is_final() && !attempts[1].is_final());
+    assert!(attempts[2].is_final());
+
+    // Итог - по блокировке после сброса, а не по исчерпанному лимиту до него
+    let decisive = decisive_attempt(&attempts).unwrap();
+    assert_eq!(decisive.index, 2);
+    assert_eq!(decisive.apply_result, Some(4));
+    let outcome = engine
+        .resolve_outcome(clock.as_ref(), decisive, TOKEN, DEVICE_ID)
+        .await;
+    assert_eq!(
+        outcome,
+        ApplyOutcome::TooLate {
+            blocked_until: Some(Deadline::parse("01/05", before_midnight(0).date_naive()))
+        }
+    );
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{TimeDelta, TimeZone};
+use chrono_tz::Asia::Shanghai;
+use micommunity_core::{
+    Config, Engine, NtpConfig, NullSink, SharedClock,
+    ntp::{ConsensusError, consensus},
+};
+use reqwest::Client;
+
+use common::ntp::{NtpBehavior, NtpResponder};
+
+// Задержка ответов, чтобы интервалы ± delay/2 не зависели от шума loopback
+const DELAY: Duration = Duration::from_millis(20);
+
+fn engine(servers: Vec<String>, min_servers: usize) -> Engine {
+    let config = Config {
+        ntp: NtpConfig {
+            servers,
+            timeout: Duration::from_millis(300),
+            min_servers,
+            ..NtpConfig::default()
+        },
+        ..Config::default()
+    };
+    Engine::with_config(Client::new(), Arc::new(NullSink), config)
+}
+
+async fn responders(offsets_ms: &[i64], delay: Duration) -> Vec<NtpResponder> {
+    let mut responders = vec![];
+    for offset in offsets_ms {
+        let responder = NtpResponder::with_offset_ms(*offset).await;
+        responder.set_delay(delay);
+        responders.push(responder);
+    }
+    responders
+}
+
+fn servers(responders: &[NtpResponder]) -> Vec<String> {
+    responders.iter().map(|r| r.server()).collect()
+}
+
+fn assert_near(actual: TimeDelta, expected: TimeDelta, tolerance: TimeDelta) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{} мс вместо {} мс",
+        actual.num_milliseconds(),
+        expected.num_milliseconds()
+    );
+}
+
+#[tokio::test]
+async fn sync_uses_server_offset() {
+    let good = responders(&[250, 250, 250], DELAY).await;
+    let clock = engine(servers(&good), 3).sync_clock().await.unwrap();
+    assert_near(
+        clock.offset(),
+        TimeDelta::milliseconds(250),
+        TimeDelta::milliseconds(10),
+    );
+    assert_near(
+        TimeDelta::from_std(clock.round_trip()).unwrap(),
+        TimeDelta::from_std(DELAY).unwrap(),
+        TimeDelta::milliseconds(10),
+    );
+}
+
+#[tokio::test]
+async fn sync_to_chosen_time() {
+    let beijing_noon = Shanghai.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+    let time = SystemTime::from(beijing_noon);
+    let mut fixed = vec![];
+    for _ in 0..3 {
+        fixed.push(NtpResponder::start(NtpBehavior::Time(time)).await);
+    }
+    let clock = engine(servers(&fixed), 3).sync_clock().await.unwrap();
+    assert_near(
+        clock.now() - beijing_noon,
+        TimeDelta::zero(),
+        TimeDelta::milliseconds(100),
+    );
+}
+
+#[tokio::test]
+async fn outlier_is_rejected() {
+    let good = responders(&[250, 250, 250], DELAY).await;
+    let outlier = NtpResponder::with_offset_ms(5000).await;
+    outlier.set_delay(DELAY);
+    let mut list = servers(&good);
+    list.push(outlier.server());
+
+    let samples = engine(list.clone(), 3).query_ntp_servers().await;
+    assert_eq!(samples.len(), 4);
+    let consensus = consensus(samples, 3, Duration::from_millis(100)).unwrap();
+    assert_eq!(consensus.accepted.len(), 3);
+    assert_eq!(consensus.rejected.len(), 1);
+    assert_eq!(consensus.rejected[0].server, outlier.server());
+    assert_near(
+        consensus.offset,
+        TimeDelta::milliseconds(250),
+        TimeDelta::milliseconds(10),
+    );
+
+    let clock = engine(list, 3).sync_clock().await.unwrap();
+    assert_near(
+        clock.offset(),
+        TimeDelta::milliseconds(250),
+        TimeDelta::milliseconds(10),
+    );
+}
+
+#[tokio::test]
+async fn failed_servers_are_skipped() {
+    let good = responders(&[0, 0, 0], DELAY).await;
+    let silent = NtpResponder::start(NtpBehavior::Silent).await;
+    let denied = NtpResponder::start(NtpBehavior::KissOfDeath).await;
+    let mut list = servers(&good);
+    list.push(silent.server());
+    list.push(denied.server());
+    list.push("not a server".to_string());
+
+    let engine = engine(list, 3);
+    assert_eq!(engine.query_ntp_servers().await.len(), 3);
+    assert!(engine.sync_clock().await.is_some());
+}
+
+#[tokio::test]
+async fn not_enough_servers() {
+    let good = responders(&[0], DELAY).await;
+    let silent = NtpResponder::start(NtpBehavior::Silent).await;
+    let mut list = servers(&good);
+    list.push(silent.server());
+
+    let engine = engine(list, 3);
+    let samples = engine.query_ntp_servers().await;
+    assert_eq!(
+        consensus(samples, 3, Duration::from_millis(100)).unwrap_err(),
+        ConsensusError::NotEnoughServers {
+            answered: 1,
+            required: 3
+        }
+    );
+    assert!(engine.sync_clock().await.is_none());
+}
+
+#[tokio::test]
+async fn no_majority() {
+    let split = responders(&[0, 1000, 2000], DELAY).await;
+    let samples = engine(servers(&split), 3).query_ntp_servers().await;
+    assert!(matches!(
+        consensus(samples, 3, Duration::from_millis(100)),
+        Err(ConsensusError::NoMajority { agreeing: 1, answered: 3 })
+    ));
+}
+
+#[tokio::test]
+async fn agreeing_servers_too_far_apart() {
+    // Интервалы ± 100 мс пересекаются, но разброс смещений 150 мс
+    let skewed = responders(&[0, 75, 150], Duration::from_millis(200)).await;
+    let engine = engine(servers(&skewed), 3);
+    let samples = engine.query_ntp_servers().await;
+    assert!(matches!(
+        consensus(samples, 3, Duration::from_millis(100)),
+        Err(ConsensusError::Disagreement { .. })
+    ));
+    assert!(engine.sync_clock().await.is_none());
+}
+
+#[tokio::test]
+async fn resync_picks_up_skew() {
+    let good = responders(&[0, 0, 0], DELAY).await;
+    let engine = engine(servers(&good), 3);
+    let clock = SharedClock::new(engine.sync_clock().await.unwrap());
+
+    for responder in &good {
+        responder.set_behavior(NtpBehavior::Offset(TimeDelta::seconds(2)));
+    }
+    assert!(engine.resync(&clock).await);
+    assert_near(
+        clock.get().offset(),
+        TimeDelta::seconds(2),
+        TimeDelta::milliseconds(10),
+    );
+}
+
+#[tokio::test]
+async fn failed_resync_keeps_clock() {
+    let good = responders(&[300, 300, 300], DELAY).await;
+    let engine = engine(servers(&good), 3);
+    let clock = SharedClock::new(engine.sync_clock().await.unwrap());
+
+    for responder in &good {
+        responder.set_behavior(NtpBehavior::Silent);
+    }
+    assert!(!engine.resync(&clock).await);
+    assert_near(
+        clock.get().offset(),
+        TimeDelta::milliseconds(300),
+        TimeDelta::milliseconds(10),
+    );
+}
@@ -0,0 +1,289 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, NaiveTime, TimeDelta, TimeZone};
+use chrono_tz::{Asia::Shanghai, Tz};
+use micommunity_core::{
+    Clock, Engine, FakeClock, NullSink, StrategyConfig,
+    network::{
+        FixedOffsetStrategy, FixedTimeStrategy, FormulaStrategy, HalfRttStrategy, SendStrategy,
+    },
+};
+use reqwest::Client;
+use tracing::Level;
+
+use common::RecordingSink;
+
+fn beijing(date: (i32, u32, u32), time: (u32, u32, u32), ms: i64) -> DateTime<Tz> {
+    Shanghai
+        .with_ymd_and_hms(date.0, date.1, date.2, time.0, time.1, time.2)
+        .unwrap()
+        + TimeDelta::milliseconds(ms)
+}
+
+fn engine(strategy: impl SendStrategy + 'static) -> Engine {
+    Engine::new(Client::new(), Arc::new(NullSink)).with_strategy(Arc::new(strategy))
+}
+
+/// Часы дошли до `expected`, но не ушли дальше, чем на погрешность таймера.
+fn assert_fired_at(clock: &FakeClock, expected: DateTime<Tz>) {
+    let now = clock.now();
+    assert!(now >= expected, "{} раньше {}", now, expected);
+    assert!(
+        now - expected < TimeDelta::milliseconds(2),
+        "{} позже {}",
+        now,
+        expected
+    );
+}
+
+#[test]
+fn formula_target_depends_on_ping() {
+    let engine = engine(FormulaStrategy);
+    let clock = FakeClock::new(beijing((2025, 3, 10), (20, 0, 0), 0));
+    assert_eq!(
+        engine.target_time(&clock, 166.0),
+        beijing((2025, 3, 10), (23, 59, 59), 91)
+    );
+    assert_eq!(
+        engine.target_time(&clock, 100.0),
+        beijing((2025, 3, 10), (23, 59, 59), 487)
+    );
+    assert_eq!(
+        engine.target_time(&clock, 300.0),
+        beijing((2025, 3, 10), (23, 59, 58), 287)
+    );
+}
+
+#[test]
+fn target_rolls_over_to_next_day_after_midnight() {
+    let engine = engine(FormulaStrategy);
+    let clock = FakeClock::new(beijing((2025, 3, 11), (0, 0, 0), 500));
+    assert_eq!(
+        engine.target_time(&clock, 166.0),
+        beijing((2025, 3, 11), (23, 59, 59), 91)
+    );
+}
+
+#[test]
+fn target_just_after_midnight_stays_today() {
+    // Отправка через 150 мс после полуночи еще впереди
+    let engine = engine(HalfRttStrategy { bias_ms: 200 });
+    let clock = FakeClock::new(beijing((2025, 3, 11), (0, 0, 0), 100));
+    assert_eq!(
+        engine.target_time(&clock, 100.0),
+        beijing((2025, 3, 11), (0, 0, 0), 150)
+    );
+}
+
+#[test]
+fn target_on_new_year_eve() {
+    let engine = engine(FormulaStrategy);
+    let clock = FakeClock::new(beijing((2025, 12, 31), (21, 0, 0), 0));
+    assert_eq!(
+        engine.target_time(&clock, 166.0),
+        beijing((2025, 12, 31), (23, 59, 59), 91)
+    );
+    assert_eq!(
+        engine.measure_time(clock.now()),
+        beijing((2025, 12, 31), (23, 59, 47), 91)
+    );
+}
+
+#[test]
+fn fixed_offset_ignores_ping() {
+    let engine = engine(FixedOffsetStrategy {
+        before_midnight: Duration::from_millis(250),
+    });
+    let clock = FakeClock::new(beijing((2025, 3, 10), (21, 0, 0), 0));
+    for ping in [20.0, 400.0] {
+        assert_eq!(
+            engine.target_time(&clock, ping),
+            beijing((2025, 3, 10), (23, 59, 59), 750)
+        );
+    }
+}
+
+#[test]
+fn fixed_time_before_and_after_midnight() {
+    let clock = FakeClock::new(beijing((2025, 3, 10), (21, 0, 0), 0));
+    let at = |h, m, s, ms| FixedTimeStrategy {
+        time: NaiveTime::from_hms_milli_opt(h, m, s, ms).unwrap(),
+    };
+    // Вечернее время - день перед полуночью, утреннее - день после нее
+    assert_eq!(
+        engine(at(23, 59, 59, 900)).target_time(&clock, 100.0),
+        beijing((2025, 3, 10), (23, 59, 59), 900)
+    );
+    assert_eq!(
+        engine(at(0, 0, 0, 120)).target_time(&clock, 100.0),
+        beijing((2025, 3, 11), (0, 0, 0), 120)
+    );
+}
+
+#[test]
+fn strategy_config_parses() {
+    let parse = |s: &str| s.parse::<StrategyConfig>();
+    assert_eq!(parse("formula"), Ok(StrategyConfig::Formula));
+    assert_eq!(
+        parse("fixed-offset:250"),
+        Ok(StrategyConfig::FixedOffset(Duration::from_millis(250)))
+    );
+    assert_eq!(
+        parse("half-rtt"),
+        Ok(StrategyConfig::HalfRtt { bias_ms: 0 })
+    );
+    assert_eq!(
+        parse("half-rtt:-40"),
+        Ok(StrategyConfig::HalfRtt { bias_ms: -40 })
+    );
+    assert_eq!(
+        parse("at:23:59:59.850"),
+        Ok(StrategyConfig::At(
+            NaiveTime::from_hms_milli_opt(23, 59, 59, 850).unwrap()
+        ))
+    );
+    assert_eq!(
+        parse("at:00:00:01"),
+        Ok(StrategyConfig::At(
+            NaiveTime::from_hms_opt(0, 0, 1).unwrap()
+        ))
+    );
+
+    for config in [
+        "formula",
+        "fixed-offset:250",
+        "half-rtt:-40",
+        "at:23:59:59.850",
+    ] {
+        assert_eq!(parse(config).unwrap().to_string(), config);
+    }
+}
+
+#[test]
+fn strategy_config_rejects_bad_input() {
+    for bad in [
+        "",
+        "soon",
+        "formula:10",
+        "fixed-offset",
+        "fixed-offset:",
+        "fixed-offset:-250",
+        "fixed-offset:1.5",
+        "half-rtt:abc",
+        "at",
+        "at:25:00:00",
+        "at:23:59",
+        "at:midnight",
+    ] {
+        assert!(
+            bad.parse::<StrategyConfig>().is_err(),
+            "'{}' разобрана",
+            bad
+        );
+    }
+    let error = "soon".parse::<StrategyConfig>().unwrap_err();
+    assert!(error.contains("неизвестная стратегия 'soon'"), "{}", error);
+    let error = "fixed-offset:x".parse::<StrategyConfig>().unwrap_err();
+    assert!(error.starts_with("fixed-offset: "), "{}", error);
+}
+
+#[test]
+fn measure_time_already_passed() {
+    let engine = engine(FormulaStrategy);
+    let now = beijing((2025, 3, 10), (23, 59, 50), 0);
+    assert_eq!(engine.measure_time(now), now);
+}
+
+#[test]
+fn measure_time_before_early_target() {
+    let now = beijing((2025, 3, 10), (21, 0, 0), 0);
+    let engine_at = |h, m, s| {
+        engine(FixedTimeStrategy {
+            time: NaiveTime::from_hms_opt(h, m, s).unwrap(),
+        })
+    };
+    // Задержка измеряется за 12 с до отправки, а не до полуночи
+    assert_eq!(
+        engine(FixedOffsetStrategy {
+            before_midnight: Duration::from_secs(15),
+        })
+        .measure_time(now),
+        beijing((2025, 3, 10), (23, 59, 33), 0)
+    );
+    assert_eq!(
+        engine_at(23, 59, 30).measure_time(now),
+        beijing((2025, 3, 10), (23, 59, 18), 0)
+    );
+    assert_eq!(
+        engine_at(23, 0, 0).measure_time(now),
+        beijing((2025, 3, 10), (22, 59, 48), 0)
+    );
+}
+
+#[test]
+fn passed_target_is_reported() {
+    let events = Arc::new(RecordingSink::default());
+    let engine =
+        Engine::new(Client::new(), events.clone()).with_strategy(Arc::new(FixedOffsetStrategy {
+            before_midnight: Duration::from_secs(15),
+        }));
+    let clock = FakeClock::new(beijing((2025, 3, 10), (23, 59, 50), 0));
+    assert_eq!(
+        engine.target_time(&clock, 100.0),
+        beijing((2025, 3, 10), (23, 59, 45), 0)
+    );
+    assert!(
+        events
+            .events()
+            .iter()
+            .any(|(level, line)| *level == Level::WARN && line.contains("уже прошло")),
+        "{:?}",
+        events.lines()
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_fired_at_early_fixed_time() {
+    let engine = engine(FixedTimeStrategy {
+        time: NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+    });
+    let clock = FakeClock::new(beijing((2025, 3, 10), (22, 30, 0), 0));
+
+    let measure_time = engine.wait_until_measure_time(&clock).await;
+    assert_eq!(measure_time, beijing((2025, 3, 10), (22, 59, 48), 0));
+    assert_fired_at(&clock, measure_time);
+
+    let target = engine.target_time(&clock, 100.0);
+    assert_eq!(target, beijing((2025, 3, 10), (23, 0, 0), 0));
+    engine.wait_until_target_time(&clock, target).await;
+    assert_fired_at(&clock, target);
+}
+
+#[tokio::test(start_paused = true)]
+async fn wait_until_fires_at_target() {
+    let engine = engine(FormulaStrategy);
+    let clock = FakeClock::new(beijing((2025, 3, 10), (20, 0, 0), 0));
+    let target = beijing((2025, 3, 10), (23, 59, 59), 91);
+    engine.wait_until(&clock, target).await;
+    assert_fired_at(&clock, target);
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_fired_at_formula_time() {
+    let engine = engine(FormulaStrategy);
+    let clock = FakeClock::new(beijing((2025, 3, 10), (22, 30, 0), 0));
+
+    let measure_time = engine.wait_until_measure_time(&clock).await;
+    assert_eq!(measure_time, beijing((2025, 3, 10), (23, 59, 47), 91));
+    assert_fired_at(&clock, measure_time);
+
+    for (ping, expected) in [(166.0, 91), (50.0, 787)] {
+        let target = engine.target_time(&clock, ping);
+        assert_eq!(target, beijing((2025, 3, 10), (23, 59, 59), expected));
+        engine.wait_until_target_time(&clock, target).await;
+        assert_fired_at(&clock, target);
+        clock.set(measure_time);
+    }
+}
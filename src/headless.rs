@@ -0,0 +1,732 @@
+// headless.rs
+// Запуск без окна (например, на сервере): токен берется из аргумента,
+// переменной окружения, файла или хранилища токенов, логи пишутся в stdout
+// и в файл.
+use std::{
+    ffi::OsString,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use clap::{
+    CommandFactory, FromArgMatches, Parser, Subcommand, error::ErrorKind, parser::ValueSource,
+};
+use micommunity_core::{
+    AccountOutcome, ApplyOutcome, Clock, Config, StrategyConfig, SystemClock, UnlockStatus,
+    deviceid,
+};
+use tracing::{Level, error};
+
+use crate::history;
+use crate::logger::{self, log};
+use crate::secrets::{PASSPHRASE_ENV, SecretError, SecretStore, mask};
+use crate::settings::Settings;
+
+#[derive(Parser, Debug)]
+#[command(name = "micommunity", version, about = "Mi Community Auto Unlock")]
+pub struct Cli {
+    /// Запустить без графического интерфейса
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Минимальный уровень сообщений в консоли: error, warn, info, debug
+    /// (в файл лога пишутся все). С --headless лог идет в stdout, иначе в stderr
+    #[arg(long, global = true, default_value_t = Level::INFO)]
+    pub log_level: Level,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// new_bbs_serviceToken
+    #[arg(long, global = true, env = "MI_SERVICE_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Файл, содержащий new_bbs_serviceToken
+    #[arg(long, global = true, conflicts_with = "token")]
+    pub token_file: Option<PathBuf>,
+
+    /// Сохраненный токен, если --token не задан (по умолчанию - активный).
+    /// С --token - аккаунт, чьи deviceId и история используются для него
+    #[arg(long, global = true)]
+    pub token_label: Option<String>,
+
+    /// Сохраненный аккаунт для очереди, можно указать несколько раз
+    #[arg(
+        long = "account",
+        value_name = "LABEL",
+        conflicts_with_all = ["token_file", "device_id"]
+    )]
+    pub accounts: Vec<String>,
+
+    /// Подать заявки для всех сохраненных аккаунтов
+    #[arg(long, conflicts_with_all = ["token_file", "device_id", "accounts"])]
+    pub all_accounts: bool,
+
+    /// deviceId для этого запуска (по умолчанию сохраненный для аккаунта)
+    #[arg(long)]
+    pub device_id: Option<String>,
+
+    /// Базовый URL API. Здесь и ниже по умолчанию - значение из настроек
+    #[arg(long)]
+    pub api_base_url: Option<String>,
+
+    /// NTP-сервер (хост:порт), можно указать несколько раз
+    #[arg(long = "ntp-server")]
+    pub ntp_servers: Vec<String>,
+
+    /// Таймаут ответа NTP-сервера, мс
+    #[arg(long)]
+    pub ntp_timeout_ms: Option<u64>,
+
+    /// Минимальное число ответивших NTP-серверов
+    #[arg(long)]
+    pub ntp_min_servers: Option<usize>,
+
+    /// Допустимый разброс смещений NTP-серверов, мс
+    #[arg(long)]
+    pub ntp_max_disagreement_ms: Option<u64>,
+
+    /// Интервал фоновой пересинхронизации времени, с (0 - отключить)
+    #[arg(long)]
+    pub ntp_resync_interval_secs: Option<u64>,
+
+    /// Число запросов в серии вокруг целевого времени
+    #[arg(long)]
+    pub burst_count: Option<usize>,
+
+    /// Интервал между запросами серии, мс
+    #[arg(long)]
+    pub burst_interval_ms: Option<u64>,
+
+    /// Стратегия отправки: formula, fixed-offset:<мс>, half-rtt[:<мс>], at:<ЧЧ:ММ:СС.ммм>,
+    /// adaptive[:<макс. поправка мс>] (формула с поправкой по истории попыток)
+    #[arg(long)]
+    pub strategy: Option<StrategyConfig>,
+
+    /// За сколько секунд до отправки по стратегии измерять задержку
+    #[arg(long)]
+    pub measure_before_secs: Option<u64>,
+
+    /// Насколько мс до или после сброса лимита запрос с apply_result 4 еще считается принятым
+    #[arg(long)]
+    pub accept_window_ms: Option<u64>,
+
+    /// Повторять заявку каждую ночь, пока она не одобрена
+    #[arg(long, conflicts_with_all = ["accounts", "all_accounts"])]
+    pub daemon: bool,
+
+    /// Сколько ночей повторять в режиме --daemon (0 - без ограничения)
+    #[arg(long)]
+    pub max_days: Option<u32>,
+
+    /// За сколько секунд до полуночи просыпаться в режиме --daemon
+    #[arg(long)]
+    pub wake_before_secs: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Управление сохраненными токенами
+    #[command(subcommand)]
+    Token(TokenCommand),
+    /// Управление deviceId аккаунта (--token-label, по умолчанию активного)
+    #[command(subcommand)]
+    DeviceId(DeviceIdCommand),
+    /// Показать историю попыток, новые первыми
+    History {
+        /// Только попытки аккаунта с этим названием
+        #[arg(long, value_name = "LABEL")]
+        account: Option<String>,
+        /// Сколько последних попыток показать (0 - все)
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Вывести записи как JSON, по одной на строку
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeviceIdCommand {
+    /// Показать сохраненный deviceId
+    Show,
+    /// Сгенерировать новый случайный deviceId
+    Regenerate,
+    /// Вычислить deviceId из строки (один и тот же на любом компьютере)
+    Seed { seed: String },
+    /// Сохранить deviceId, скопированный из приложения Mi Community
+    Import { device_id: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TokenCommand {
+    /// Сохранить токен (из --token, --token-file, MI_SERVICE_TOKEN или stdin)
+    Add { label: String },
+    /// Показать сохраненные токены в маскированном виде
+    List,
+    /// Заменить значение сохраненного токена
+    Rotate { label: String },
+    /// Удалить сохраненный токен
+    Remove { label: String },
+}
+
+impl Cli {
+    /// Разбирает аргументы как `try_parse_from`, но отклоняет --token в
+    /// командной строке вместе с очередью: очередь берет токены из хранилища.
+    /// MI_SERVICE_TOKEN в окружении очереди не мешает.
+    pub fn try_parse_args<I, T>(args: I) -> Result<Cli, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Cli::command();
+        let matches = command.try_get_matches_from_mut(args)?;
+        let cli = Cli::from_arg_matches(&matches).map_err(|e| e.format(&mut command))?;
+        if (cli.all_accounts || !cli.accounts.is_empty())
+            && matches.value_source("token") == Some(ValueSource::CommandLine)
+        {
+            return Err(command.error(
+                ErrorKind::ArgumentConflict,
+                "--token нельзя использовать с --account и --all-accounts: токены очереди берутся из хранилища",
+            ));
+        }
+        Ok(cli)
+    }
+
+    /// Настройки движка из settings.json с заданными в командной строке
+    /// параметрами поверх.
+    pub fn config(&self, settings: &Settings) -> Config {
+        let mut config = settings.config();
+        if let Some(base_url) = &self.api_base_url {
+            config.api.base_url = base_url.clone();
+        }
+        if !self.ntp_servers.is_empty() {
+            config.ntp.servers = self.ntp_servers.clone();
+        }
+        if let Some(ms) = self.ntp_timeout_ms {
+            config.ntp.timeout = Duration::from_millis(ms);
+        }
+        if let Some(min_servers) = self.ntp_min_servers {
+            config.ntp.min_servers = min_servers;
+        }
+        if let Some(ms) = self.ntp_max_disagreement_ms {
+            config.ntp.max_disagreement = Duration::from_millis(ms);
+        }
+        if let Some(secs) = self.ntp_resync_interval_secs {
+            config.ntp.resync_interval = Duration::from_secs(secs);
+        }
+        if let Some(count) = self.burst_count {
+            config.burst.count = count;
+        }
+        if let Some(ms) = self.burst_interval_ms {
+            config.burst.interval = Duration::from_millis(ms);
+        }
+        if let Some(strategy) = &self.strategy {
+            config.schedule.strategy = strategy.clone();
+        }
+        if let Some(secs) = self.measure_before_secs {
+            config.schedule.measure_before = Duration::from_secs(secs);
+        }
+        if let Some(ms) = self.accept_window_ms {
+            config.schedule.accept_window = Duration::from_millis(ms);
+        }
+        if let Some(max_days) = self.max_days {
+            config.retry.max_days = max_days;
+        }
+        if let Some(secs) = self.wake_before_secs {
+            config.retry.wake_before = Duration::from_secs(secs);
+        }
+        config
+    }
+}
+
+// Коды выхода процесса. 2 зарезервирован clap для ошибок аргументов.
+pub const EXIT_ACCEPTED: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_NOT_ELIGIBLE: i32 = 3;
+pub const EXIT_TIME_SYNC_FAILED: i32 = 4;
+pub const EXIT_REQUEST_FAILED: i32 = 5;
+pub const EXIT_REJECTED: i32 = 6;
+pub const EXIT_TOO_LATE: i32 = 7;
+pub const EXIT_UNKNOWN: i32 = 8;
+pub const EXIT_TOKEN_EXPIRED: i32 = 9;
+pub const EXIT_QUOTA_EXHAUSTED: i32 = 10;
+pub const EXIT_BLOCKED: i32 = 11;
+pub const EXIT_TOO_YOUNG: i32 = 12;
+
+pub fn exit_code(outcome: &ApplyOutcome) -> i32 {
+    match outcome {
+        ApplyOutcome::Approved { .. } | ApplyOutcome::Accepted => EXIT_ACCEPTED,
+        ApplyOutcome::QuotaExhausted { .. } => EXIT_QUOTA_EXHAUSTED,
+        ApplyOutcome::TooLate { .. } => EXIT_TOO_LATE,
+        ApplyOutcome::NotEligible(UnlockStatus::Blocked { .. }) => EXIT_BLOCKED,
+        ApplyOutcome::NotEligible(UnlockStatus::TooYoung) => EXIT_TOO_YOUNG,
+        ApplyOutcome::NotEligible(UnlockStatus::RequestFailed(_)) => EXIT_REQUEST_FAILED,
+        ApplyOutcome::NotEligible(UnlockStatus::TokenExpired) | ApplyOutcome::TokenExpired => {
+            EXIT_TOKEN_EXPIRED
+        }
+        ApplyOutcome::NotEligible(_) => EXIT_NOT_ELIGIBLE,
+        ApplyOutcome::RequestRejected => EXIT_REJECTED,
+        ApplyOutcome::TimeSyncFailed => EXIT_TIME_SYNC_FAILED,
+        ApplyOutcome::RequestFailed(_) => EXIT_REQUEST_FAILED,
+        ApplyOutcome::UnknownApplyResult(_) | ApplyOutcome::UnknownCode(_) => EXIT_UNKNOWN,
+    }
+}
+
+/// Код выхода очереди: 0, если все аккаунты успешны, иначе код первого неудачного.
+pub fn queue_exit_code(results: &[AccountOutcome]) -> i32 {
+    results
+        .iter()
+        .map(|result| exit_code(&result.outcome))
+        .find(|code| *code != EXIT_ACCEPTED)
+        .unwrap_or(EXIT_ACCEPTED)
+}
+
+fn read_token(cli: &Cli) -> Option<String> {
+    if let Some(path) = &cli.token_file {
+        return match std::fs::read_to_string(path) {
+            Ok(token) => Some(token),
+            Err(e) => {
+                error!("Ошибка чтения файла {}: {}", path.display(), e);
+                None
+            }
+        };
+    }
+    cli.token.clone()
+}
+
+fn prompt(message: &str) -> Option<String> {
+    eprint!("{}", message);
+    io::stderr().flush().ok()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).ok()?;
+    Some(line.trim().to_string())
+}
+
+/// Открывает хранилище токенов. Пароль файла берется из окружения или
+/// запрашивается в терминале.
+fn open_store() -> Result<SecretStore, SecretError> {
+    match SecretStore::open(None) {
+        Err(SecretError::NoPassphrase) => {
+            let passphrase = prompt(&format!(
+                "Пароль файла токенов (или задайте {}): ",
+                PASSPHRASE_ENV
+            ));
+            SecretStore::open(passphrase)
+        }
+        result => result,
+    }
+}
+
+/// Токен из хранилища по `--token-label` или активный из настроек.
+fn stored_token(cli: &Cli, settings: &Settings) -> Option<String> {
+    let label = cli
+        .token_label
+        .clone()
+        .unwrap_or_else(|| settings.active_token.clone());
+    let store = match open_store() {
+        Ok(store) => store,
+        Err(e) => {
+            error!("Ошибка открытия хранилища токенов: {}", e);
+            return None;
+        }
+    };
+    match store.get(&label) {
+        Ok(Some(token)) => {
+            log(format!("Используется сохраненный токен \"{}\"", label));
+            Some(token)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            error!("Ошибка чтения токена \"{}\": {}", label, e);
+            None
+        }
+    }
+}
+
+pub fn run_token_command(cli: &Cli, command: &TokenCommand) -> i32 {
+    let store = match open_store() {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Ошибка открытия хранилища токенов: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let mut settings = Settings::load();
+    let new_token = || {
+        read_token(cli)
+            .or_else(|| prompt("new_bbs_serviceToken: "))
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty())
+    };
+
+    let result = match command {
+        TokenCommand::Add { label } | TokenCommand::Rotate { label } => {
+            let exists = settings.token_labels.contains(label);
+            if matches!(command, TokenCommand::Add { .. }) && exists {
+                eprintln!("Токен \"{}\" уже есть, используйте rotate", label);
+                return EXIT_ERROR;
+            }
+            if matches!(command, TokenCommand::Rotate { .. }) && !exists {
+                eprintln!("Токен \"{}\" не найден", label);
+                return EXIT_ERROR;
+            }
+            let Some(token) = new_token() else {
+                eprintln!("Ошибка: токен пустой");
+                return EXIT_ERROR;
+            };
+            store.set(label, &token).map(|()| {
+                if settings.token_labels.is_empty() {
+                    settings.active_token = label.clone();
+                }
+                settings.add_token_label(label);
+                println!(
+                    "Токен \"{}\" сохранен ({}): {}",
+                    label,
+                    store.describe(),
+                    mask(&token)
+                );
+            })
+        }
+        TokenCommand::List => {
+            if settings.token_labels.is_empty() {
+                println!("Сохраненных токенов нет");
+            }
+            for label in &settings.token_labels {
+                let active = if *label == settings.active_token { "*" } else { " " };
+                match store.get(label) {
+                    Ok(Some(token)) => println!("{} {}\t{}", active, label, mask(&token)),
+                    Ok(None) => println!("{} {}\t(нет в хранилище)", active, label),
+                    Err(e) => println!("{} {}\t(ошибка: {})", active, label, e),
+                }
+            }
+            Ok(())
+        }
+        TokenCommand::Remove { label } => store.remove(label).map(|()| {
+            settings.remove_token_label(label);
+            println!("Токен \"{}\" удален", label);
+        }),
+    };
+    if let Err(e) = result {
+        eprintln!("Ошибка хранилища токенов: {}", e);
+        return EXIT_ERROR;
+    }
+    if let Err(e) = settings.save() {
+        eprintln!("Ошибка сохранения настроек: {}", e);
+        return EXIT_ERROR;
+    }
+    EXIT_ACCEPTED
+}
+
+pub fn run_device_id_command(cli: &Cli, command: &DeviceIdCommand) -> i32 {
+    let mut settings = Settings::load();
+    let label = cli
+        .token_label
+        .clone()
+        .unwrap_or_else(|| settings.active_token.clone());
+    let device_id = match command {
+        DeviceIdCommand::Show => settings.ensure_device_id(&label),
+        DeviceIdCommand::Regenerate => deviceid::generate_device_id(),
+        DeviceIdCommand::Seed { seed } => deviceid::device_id_from_seed(seed),
+        DeviceIdCommand::Import { device_id } => match deviceid::parse_device_id(device_id) {
+            Ok(device_id) => device_id,
+            Err(e) => {
+                eprintln!("Ошибка: {}", e);
+                return EXIT_ERROR;
+            }
+        },
+    };
+    settings.set_device_id(&label, device_id.clone());
+    if let Err(e) = settings.save() {
+        eprintln!("Ошибка сохранения настроек: {}", e);
+        return EXIT_ERROR;
+    }
+    println!("{}\t{}", label, device_id);
+    EXIT_ACCEPTED
+}
+
+pub fn run_history_command(account: Option<&str>, limit: usize, json: bool) -> i32 {
+    let records = match history::recent(account, limit) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Ошибка чтения истории: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    for record in &records {
+        if json {
+            match serde_json::to_string(record) {
+                Ok(line) => println!("{}", line),
+                Err(e) => eprintln!("Ошибка вывода записи: {}", e),
+            }
+        } else {
+            println!("{}", record);
+        }
+    }
+    EXIT_ACCEPTED
+}
+
+async fn run_queue(cli: Cli, mut settings: Settings) -> i32 {
+    let labels = if cli.all_accounts {
+        settings.token_labels.clone()
+    } else {
+        cli.accounts.clone()
+    };
+    let store = match open_store() {
+        Ok(store) => store,
+        Err(e) => {
+            error!("Ошибка открытия хранилища токенов: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let accounts = settings.saved_accounts(&store, &labels);
+    if accounts.is_empty() {
+        error!("Ошибка: нет аккаунтов для очереди (сохраните токены командой token add)");
+        return EXIT_ERROR;
+    }
+
+    let engine = history::engine(cli.config(&settings));
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let results = engine.submit_all(&clock, &accounts).await;
+    queue_exit_code(&results)
+}
+
+pub async fn run(cli: Cli) -> i32 {
+    logger::init_file();
+    log("Программа запустилась (без окна)!");
+
+    let mut settings = Settings::load();
+    if cli.all_accounts || !cli.accounts.is_empty() {
+        return run_queue(cli, settings).await;
+    }
+    let explicit_token = read_token(&cli);
+    let from_store = explicit_token.is_none();
+    let cookie_value = match explicit_token.or_else(|| stored_token(&cli, &settings)) {
+        Some(token) => token.trim().to_string(),
+        None => String::new(),
+    };
+    if cookie_value.is_empty() {
+        error!(
+            "Ошибка: Cookie пустой (укажите --token, --token-file, MI_SERVICE_TOKEN или сохраните токен командой token add)"
+        );
+        return EXIT_ERROR;
+    }
+
+    // Аккаунт запуска: его deviceId и под его названием попытка попадает в
+    // историю. Токен из аргументов без --token-label ни к какому сохраненному
+    // аккаунту не относится, его deviceId выводится из самого токена
+    let label = match &cli.token_label {
+        Some(label) => Some(label.clone()),
+        None if from_store => Some(settings.active_token.clone()),
+        None => None,
+    };
+    let device_id = cli.device_id.as_deref().map(deviceid::parse_device_id);
+    let device_id = match (device_id, &label) {
+        (Some(Ok(device_id)), _) => device_id,
+        (Some(Err(e)), _) => {
+            error!("Ошибка: {}", e);
+            return EXIT_ERROR;
+        }
+        (None, Some(label)) => {
+            let device_id = settings.ensure_device_id(label);
+            if let Err(e) = settings.save() {
+                error!("Ошибка сохранения настроек: {}", e);
+            }
+            device_id
+        }
+        (None, None) => {
+            // Не сохраняется, но от запуска к запуску тот же, пока не сменится токен
+            log(
+                "Токен без --token-label: deviceId вычислен из токена (задайте --token-label или --device-id, чтобы выбрать его)",
+            );
+            deviceid::device_id_from_seed(&cookie_value)
+        }
+    };
+
+    let mut engine = history::engine(cli.config(&settings));
+    if let Some(label) = &label {
+        engine = engine.with_account(label);
+    }
+    let outcome = if cli.daemon {
+        engine
+            .run_daemon(&SystemClock, &cookie_value, &device_id)
+            .await
+    } else {
+        engine.submit(&SystemClock, &cookie_value, &device_id).await
+    };
+    log(format!("Итог: {}", outcome));
+    exit_code(&outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_covers_every_outcome() {
+        let cases = [
+            (ApplyOutcome::Approved { deadline: None }, EXIT_ACCEPTED),
+            (ApplyOutcome::Accepted, EXIT_ACCEPTED),
+            (
+                ApplyOutcome::QuotaExhausted { retry_at: None },
+                EXIT_QUOTA_EXHAUSTED,
+            ),
+            (
+                ApplyOutcome::TooLate {
+                    blocked_until: None,
+                },
+                EXIT_TOO_LATE,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::Approved { deadline: None }),
+                EXIT_NOT_ELIGIBLE,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::CanApply),
+                EXIT_NOT_ELIGIBLE,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::Blocked { until: None }),
+                EXIT_BLOCKED,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::TooYoung),
+                EXIT_TOO_YOUNG,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::TokenExpired),
+                EXIT_TOKEN_EXPIRED,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::UnknownCode(1)),
+                EXIT_NOT_ELIGIBLE,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::UnknownState {
+                    is_pass: 2,
+                    button_state: None,
+                }),
+                EXIT_NOT_ELIGIBLE,
+            ),
+            (
+                ApplyOutcome::NotEligible(UnlockStatus::RequestFailed("timeout".into())),
+                EXIT_REQUEST_FAILED,
+            ),
+            (ApplyOutcome::TokenExpired, EXIT_TOKEN_EXPIRED),
+            (ApplyOutcome::RequestRejected, EXIT_REJECTED),
+            (ApplyOutcome::TimeSyncFailed, EXIT_TIME_SYNC_FAILED),
+            (
+                ApplyOutcome::RequestFailed("timeout".into()),
+                EXIT_REQUEST_FAILED,
+            ),
+            (ApplyOutcome::UnknownApplyResult(9), EXIT_UNKNOWN),
+            (ApplyOutcome::UnknownCode(1), EXIT_UNKNOWN),
+        ];
+        for (outcome, code) in cases {
+            assert_eq!(exit_code(&outcome), code, "{:?}", outcome);
+        }
+    }
+
+    #[test]
+    fn cli_flags_override_saved_settings() {
+        let settings = Settings {
+            api_base_url: "http://saved.example".to_string(),
+            ntp_servers: vec!["saved.ntp:123".to_string()],
+            burst_count: 7,
+            burst_interval_ms: 80,
+            strategy: "half-rtt:-40".to_string(),
+            measure_before_secs: 30,
+            retry_max_days: 5,
+            ..Settings::default()
+        };
+        let cli = Cli::try_parse_from([
+            "micommunity",
+            "--headless",
+            "--burst-count",
+            "3",
+            "--strategy",
+            "fixed-offset:250",
+            "--ntp-server",
+            "cli.ntp:123",
+        ])
+        .unwrap();
+        let config = cli.config(&settings);
+
+        // Заданные флаги
+        assert_eq!(config.burst.count, 3);
+        assert_eq!(
+            config.schedule.strategy,
+            StrategyConfig::FixedOffset(Duration::from_millis(250))
+        );
+        assert_eq!(config.ntp.servers, ["cli.ntp:123"]);
+        // Незаданные - из настроек, а не значения по умолчанию
+        assert_eq!(config.api.base_url, "http://saved.example");
+        assert_eq!(config.burst.interval, Duration::from_millis(80));
+        assert_eq!(config.schedule.measure_before, Duration::from_secs(30));
+        assert_eq!(config.retry.max_days, 5);
+    }
+
+    #[test]
+    fn cli_without_flags_keeps_saved_settings() {
+        let settings = Settings {
+            burst_count: 7,
+            strategy: "half-rtt:-40".to_string(),
+            ..Settings::default()
+        };
+        let cli = Cli::try_parse_from(["micommunity", "--headless"]).unwrap();
+        let config = cli.config(&settings);
+        assert_eq!(config.burst.count, 7);
+        assert_eq!(
+            config.schedule.strategy,
+            StrategyConfig::HalfRtt { bias_ms: -40 }
+        );
+        assert_eq!(config.ntp.servers, settings.ntp_servers);
+    }
+
+    #[test]
+    fn device_id_command_takes_token_label() {
+        let cli = Cli::try_parse_from(["micommunity", "device-id", "show", "--token-label", "x"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::DeviceId(DeviceIdCommand::Show))
+        ));
+        assert_eq!(cli.token_label.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn queue_rejects_token_argument() {
+        for queue in [
+            ["--account", "main"].as_slice(),
+            ["--all-accounts"].as_slice(),
+        ] {
+            let args = ["micommunity", "--headless", "--token", "x"]
+                .iter()
+                .chain(queue);
+            let e = Cli::try_parse_args(args).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::ArgumentConflict);
+        }
+        let cli = Cli::try_parse_args(["micommunity", "--headless", "--account", "main"]).unwrap();
+        assert_eq!(cli.accounts, ["main"]);
+        assert!(Cli::try_parse_args(["micommunity", "--headless", "--token", "x"]).is_ok());
+    }
+
+    #[test]
+    fn queue_exit_code_is_first_failure() {
+        let result = |outcome| AccountOutcome {
+            label: "a".into(),
+            outcome,
+        };
+        assert_eq!(queue_exit_code(&[]), EXIT_ACCEPTED);
+        assert_eq!(
+            queue_exit_code(&[
+                result(ApplyOutcome::Accepted),
+                result(ApplyOutcome::TimeSyncFailed),
+                result(ApplyOutcome::RequestRejected),
+            ]),
+            EXIT_TIME_SYNC_FAILED
+        );
+    }
+}
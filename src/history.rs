@@ -0,0 +1,57 @@
+// history.rs
+// История попыток в папке конфигурации рядом с настройками
+// (`<config dir>/micommunity/history.jsonl`) и ее вывод для окна и CLI.
+use std::{io, sync::Arc};
+
+use micommunity_core::{AttemptRecord, Config, Engine, HistoryStore};
+use reqwest::Client;
+use tracing::error;
+
+use crate::logger::LoggerSink;
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+pub fn store() -> Option<HistoryStore> {
+    dirs::config_dir().map(|dir| HistoryStore::new(dir.join("micommunity").join(HISTORY_FILE)))
+}
+
+/// Движок с выводом в лог, который подбирает стратегию по истории.
+pub fn engine(config: Config) -> Engine {
+    let engine = Engine::with_config(Client::new(), Arc::new(LoggerSink), config);
+    match store() {
+        Some(store) => engine.with_history(store),
+        None => engine,
+    }
+}
+
+/// Дописывает попытку в историю. Ошибка записи не прерывает работу.
+pub fn record(record: &AttemptRecord) {
+    let Some(store) = store() else {
+        return;
+    };
+    if let Err(e) = store.append(record) {
+        error!("Ошибка записи истории {}: {}", store.path().display(), e);
+    }
+}
+
+/// Последние `limit` попыток (0 - все), новые первыми. `account` - только
+/// попытки этого аккаунта.
+pub fn recent(account: Option<&str>, limit: usize) -> io::Result<Vec<AttemptRecord>> {
+    let Some(store) = store() else {
+        return Ok(vec![]);
+    };
+    store.recent(account, limit)
+}
+
+/// История текстом для окна: по строке на попытку.
+pub fn summary() -> String {
+    match recent(None, 0) {
+        Ok(records) if records.is_empty() => "История пуста".to_string(),
+        Ok(records) => records
+            .iter()
+            .map(|record| record.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(e) => format!("Ошибка чтения истории: {}", e),
+    }
+}
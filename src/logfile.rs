@@ -0,0 +1,220 @@
+// logfile.rs
+// Файл лога для каждого запуска в папке данных
+// (`<data dir>/micommunity/logs/micommunity-<дата>-<время>.log`). Большой лог
+// продолжается в следующей части, старые файлы удаляются.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+
+const LOG_DIR: &str = "logs";
+const LOG_PREFIX: &str = "micommunity-";
+const LOG_EXTENSION: &str = "log";
+// Размер части, после которого лог продолжается в новом файле
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+// Сколько последних файлов лога хранить
+const MAX_FILES: usize = 20;
+
+pub struct LogFile {
+    dir: PathBuf,
+    stem: String,
+    part: u32,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    /// Папка логов по умолчанию.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("micommunity").join(LOG_DIR))
+    }
+
+    /// Открывает новый файл лога в `dir` и удаляет самые старые.
+    pub fn create(dir: &Path) -> io::Result<LogFile> {
+        fs::create_dir_all(dir)?;
+        let stem = format!("{}{}", LOG_PREFIX, Local::now().format("%Y%m%d-%H%M%S"));
+        let path = dir.join(format!("{}.{}", stem, LOG_EXTENSION));
+        let file = open(&path)?;
+        let size = file.metadata()?.len();
+        let log_file = LogFile {
+            dir: dir.to_path_buf(),
+            stem,
+            part: 1,
+            file,
+            size,
+        };
+        log_file.prune();
+        Ok(log_file)
+    }
+
+    pub fn path(&self) -> PathBuf {
+        if self.part == 1 {
+            self.dir.join(format!("{}.{}", self.stem, LOG_EXTENSION))
+        } else {
+            self.dir
+                .join(format!("{}.{}.{}", self.stem, self.part, LOG_EXTENSION))
+        }
+    }
+
+    /// Дописывает строку, переходя к новой части, если текущая заполнена.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size >= MAX_FILE_SIZE {
+            self.part += 1;
+            self.file = open(&self.path())?;
+            self.size = 0;
+            self.prune();
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    // Удаляет все файлы лога, кроме MAX_FILES последних. Имена начинаются с
+    // даты, поэтому порядок имен совпадает с порядком записи.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut logs: Vec<(String, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == LOG_EXTENSION))
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.to_string();
+                name.starts_with(LOG_PREFIX)
+                    .then_some((sort_key(&name), path))
+            })
+            .collect();
+        logs.sort();
+        let excess = logs.len().saturating_sub(MAX_FILES);
+        for (_, path) in logs.into_iter().take(excess) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Ключ сортировки: время запуска и номер части (`.2.log` идет после `.log`)
+fn sort_key(name: &str) -> String {
+    let name = name.trim_end_matches(&format!(".{}", LOG_EXTENSION));
+    match name.split_once('.') {
+        Some((stem, part)) => format!("{}.{:0>6}", stem, part),
+        None => format!("{}.{:0>6}", name, 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "micommunity-logfile-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn log_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort_by_key(|name| sort_key(name));
+        names
+    }
+
+    #[test]
+    fn sort_key_orders_parts_after_their_run() {
+        assert_eq!(
+            sort_key("micommunity-20250101-235959.log"),
+            "micommunity-20250101-235959.000001"
+        );
+        assert_eq!(
+            sort_key("micommunity-20250101-235959.2.log"),
+            "micommunity-20250101-235959.000002"
+        );
+        let mut names = [
+            "micommunity-20250102-000000.log",
+            "micommunity-20250101-235959.10.log",
+            "micommunity-20250101-235959.2.log",
+            "micommunity-20250101-235959.log",
+        ];
+        names.sort_by_key(|name| sort_key(name));
+        assert_eq!(
+            names,
+            [
+                "micommunity-20250101-235959.log",
+                "micommunity-20250101-235959.2.log",
+                "micommunity-20250101-235959.10.log",
+                "micommunity-20250102-000000.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn full_file_continues_in_next_part() {
+        let dir = temp_dir("rotation");
+        let mut log_file = LogFile::create(&dir).unwrap();
+        let first = log_file.path();
+        log_file.write_line("первая строка").unwrap();
+        log_file
+            .write_line(&"x".repeat(MAX_FILE_SIZE as usize))
+            .unwrap();
+        assert_eq!(log_file.path(), first);
+
+        log_file.write_line("после ротации").unwrap();
+        let second = log_file.path();
+        assert_ne!(second, first);
+        assert!(second.to_string_lossy().ends_with(".2.log"));
+        assert_eq!(fs::read_to_string(&second).unwrap(), "после ротации\n");
+        assert!(
+            fs::read_to_string(&first)
+                .unwrap()
+                .starts_with("первая строка\n")
+        );
+
+        let file_name = |path: &Path| path.file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(log_names(&dir), [file_name(&first), file_name(&second)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn old_logs_are_pruned() {
+        let dir = temp_dir("prune");
+        fs::create_dir_all(&dir).unwrap();
+        for day in 1..=MAX_FILES + 5 {
+            let name = format!("{}202001{:02}-120000.{}", LOG_PREFIX, day, LOG_EXTENSION);
+            fs::write(dir.join(name), "").unwrap();
+        }
+        // Чужие файлы в папке не трогаем
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join("other.log"), "").unwrap();
+
+        let log_file = LogFile::create(&dir).unwrap();
+        let names = log_names(&dir);
+        let logs: Vec<&String> = names
+            .iter()
+            .filter(|name| name.starts_with(LOG_PREFIX))
+            .collect();
+        assert_eq!(logs.len(), MAX_FILES);
+        // Остались самые новые, включая файл этого запуска
+        assert_eq!(logs[0], &format!("{}20200107-120000.log", LOG_PREFIX));
+        assert_eq!(
+            logs.last().unwrap().as_str(),
+            log_file.path().file_name().unwrap()
+        );
+        assert!(names.contains(&"notes.txt".to_string()));
+        assert!(names.contains(&"other.log".to_string()));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,40 +1,284 @@
+// logger.rs
+// Лог на tracing: слой LogLayer раздает события в файл запуска, консоль и окно.
+// Файл получает все уровни вместе с полями span (аккаунт, номер попытки,
+// задержка, время отправки), консоль и окно - от выбранного уровня. В консоли
+// лог идет в stderr, а в stdout - только при --headless, чтобы не смешиваться
+// с выводом подкоманд.
 use chrono::Local;
+use micommunity_core::{
+    AttemptRecord, Clock, Deadline, EventSink, SystemClock, deadline::format_remaining,
+};
 use once_cell::sync::OnceCell;
 use slint::{ComponentHandle, Weak};
-use std::sync::Mutex;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    io::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+use tracing::{
+    Event, Level, Metadata, Subscriber,
+    field::{Field, Visit},
+    span,
+};
+use tracing_subscriber::{Layer, layer::Context, prelude::*, registry::LookupSpan};
 
 use crate::MainWindow;
+use crate::logfile::LogFile;
 
-#[allow(dead_code, unused_variables, unused_imports)]
+/// Уровни в порядке списка в окне.
+pub const LEVELS: [Level; 4] = [Level::DEBUG, Level::INFO, Level::WARN, Level::ERROR];
 
 // Global storage for our window reference
+#[allow(dead_code, unused_variables, unused_imports)]
 static WINDOW: OnceCell<Mutex<Option<Weak<MainWindow>>>> = OnceCell::new();
 
-pub fn init(window: &MainWindow) {
+// Последние строки окна с уровнями: при смене фильтра текст собирается заново
+static WINDOW_LINES: Mutex<VecDeque<(Level, String)>> = Mutex::new(VecDeque::new());
+// Сколько строк хранить для окна; полный лог остается в файле
+const WINDOW_MAX_LINES: usize = 5000;
+// Сколько самых старых строк убирать за раз
+const WINDOW_TRIM_LINES: usize = 500;
+
+// Минимальные уровни окна и консоли (индексы в LEVELS)
+static WINDOW_LEVEL: AtomicUsize = AtomicUsize::new(1);
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(1);
+// Писать консольный лог в stdout вместо stderr
+static CONSOLE_STDOUT: AtomicBool = AtomicBool::new(false);
+
+// Файл лога текущего запуска
+static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+// Последняя дата из ответа сервера для обратного отсчета
+static COUNTDOWN: Mutex<Option<(String, Deadline)>> = Mutex::new(None);
+
+/// Подключает LogLayer. До вызова события tracing никуда не попадают.
+/// Консольный лог идет в stderr, а при `to_stdout` - в stdout.
+pub fn init(console_level: Level, to_stdout: bool) {
+    CONSOLE_LEVEL.store(level_index(console_level), Ordering::Relaxed);
+    CONSOLE_STDOUT.store(to_stdout, Ordering::Relaxed);
+    let _ = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(LogLayer));
+}
+
+pub fn init_window(window: &MainWindow) {
     let window_weak = window.as_weak();
     WINDOW.get_or_init(|| Mutex::new(Some(window_weak)));
 }
 
-// Log a message to the window
+/// Включает запись лога в файл в папке данных и сохранение паники в нем.
+pub fn init_file() {
+    let Some(dir) = LogFile::default_dir() else {
+        tracing::warn!("Папка данных не найдена, лог в файл не пишется");
+        return;
+    };
+    let file = match LogFile::create(&dir) {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Ошибка создания лога в {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    let path = file.path();
+    *FILE.lock().unwrap() = Some(file);
+    log(format!("Лог пишется в {}", path.display()));
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("Аварийное завершение: {}", info);
+        default_hook(info);
+    }));
+}
+
+pub fn level_index(level: Level) -> usize {
+    LEVELS.iter().position(|l| *l == level).unwrap_or(0)
+}
+
+pub fn level_at(index: i32) -> Level {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| LEVELS.get(index).copied())
+        .unwrap_or(Level::INFO)
+}
+
+/// Меняет минимальный уровень окна и показывает заново уже записанные строки.
+pub fn set_window_level(level: Level) {
+    WINDOW_LEVEL.store(level_index(level), Ordering::Relaxed);
+    show_lines(&WINDOW_LINES.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+// Показывает в окне сохраненные строки от выбранного уровня. Вызывается под
+// блокировкой строк, чтобы новые строки не обогнали пересборку
+fn show_lines(lines: &VecDeque<(Level, String)>) {
+    let level = LEVELS[WINDOW_LEVEL.load(Ordering::Relaxed)];
+    let text: String = lines
+        .iter()
+        .filter(|(line_level, _)| *line_level <= level)
+        .map(|(_, line)| line.as_str())
+        .collect();
+    update_window(move |window| window.set_logs(text.into()));
+}
+
+fn passes(level: Level, min: &AtomicUsize) -> bool {
+    level <= LEVELS[min.load(Ordering::Relaxed)]
+}
+
+fn update_window(update: impl FnOnce(&MainWindow) + Send + 'static) {
+    let Some(window_lock) = WINDOW.get() else {
+        return;
+    };
+    if let Ok(window_ref) = window_lock.lock()
+        && let Some(window_weak) = window_ref.clone()
+    {
+        // После закрытия окна цикл событий остановлен - остаются файл и консоль
+        let _ = slint::invoke_from_event_loop(move || {
+            if let Some(window) = window_weak.upgrade() {
+                update(&window);
+            }
+        });
+    }
+}
+
+// Строка уровня INFO; для остальных уровней - макросы tracing
 pub fn log<T: std::fmt::Display>(message: T) {
-    if let Some(window_lock) = WINDOW.get() {
-        if let Ok(window_ref) = window_lock.lock() {
-            if let Some(window_weak) = window_ref.clone() {
-                let message_str = format!("{}", message);
-                let now = Local::now();
-                let timestamp = now.format("%H:%M:%S").to_string();
-                let formatted_log = format!("[{}] {}\n", timestamp, message_str);
-
-                slint::invoke_from_event_loop(move || {
-                    if let Some(window) = window_weak.upgrade() {
-                        let current_logs = window.get_logs();
-                        let updated_logs = format!("{}{}", current_logs, formatted_log);
-                        window.set_logs(updated_logs.into());
-                    }
-                })
-                .unwrap();
+    tracing::info!("{}", message);
+}
+
+/// Раздает события tracing из этого приложения и движка.
+struct LogLayer;
+
+// Поля span в виде `имя=значение`, накопленные при создании и record
+struct SpanFields(String);
+
+#[derive(Default)]
+struct FieldsVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldsVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S> Layer<S> for LogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        metadata.target().starts_with("micommunity") && *metadata.level() <= Level::DEBUG
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldsVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldsVisitor::default();
+        values.record(&mut visitor);
+        if let Some(span) = ctx.span(id)
+            && let Some(fields) = span.extensions_mut().get_mut::<SpanFields>()
+        {
+            fields.0.push_str(&visitor.fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        let mut visitor = FieldsVisitor::default();
+        event.record(&mut visitor);
+        let message = format!("{}{}", visitor.message, visitor.fields);
+
+        let mut scope = String::new();
+        if let Some(spans) = ctx.event_scope(event) {
+            for span in spans.from_root() {
+                let extensions = span.extensions();
+                let fields = extensions.get::<SpanFields>().map_or("", |f| f.0.trim());
+                let _ = write!(scope, "{}{{{}}}: ", span.name(), fields);
             }
         }
+        write_line(level, &message, &scope);
+    }
+}
+
+fn write_line(level: Level, message: &str, scope: &str) {
+    let now = Local::now();
+    write_file(&format!(
+        "[{}] {:<5} {}{}",
+        now.format("%Y-%m-%d %H:%M:%S%.3f"),
+        level.as_str(),
+        scope,
+        message
+    ));
+
+    let line = if level == Level::INFO {
+        format!("[{}] {}\n", now.format("%H:%M:%S"), message)
+    } else {
+        format!("[{}] {} {}\n", now.format("%H:%M:%S"), level, message)
+    };
+    if passes(level, &CONSOLE_LEVEL) {
+        let _ = if CONSOLE_STDOUT.load(Ordering::Relaxed) {
+            std::io::stdout().write_all(line.as_bytes())
+        } else {
+            std::io::stderr().write_all(line.as_bytes())
+        };
+    }
+    if WINDOW.get().is_none() {
+        return;
+    }
+    let mut lines = WINDOW_LINES.lock().unwrap_or_else(|e| e.into_inner());
+    lines.push_back((level, line.clone()));
+    if lines.len() > WINDOW_MAX_LINES {
+        // Убираем старые строки пачкой, чтобы не пересобирать текст окна на
+        // каждой новой строке
+        lines.drain(..WINDOW_TRIM_LINES);
+        show_lines(&lines);
+    } else if passes(level, &WINDOW_LEVEL) {
+        update_window(move |window| {
+            let current_logs = window.get_logs();
+            window.set_logs(format!("{}{}", current_logs, line).into());
+        });
+    }
+}
+
+fn write_file(line: &str) {
+    // Паника во время записи не должна блокировать следующие сообщения
+    let mut file = FILE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(log_file) = file.as_mut()
+        && let Err(e) = log_file.write_line(line)
+    {
+        let path = log_file.path();
+        *file = None;
+        drop(file);
+        // Событие tracing изнутри слоя было бы потеряно - пишем напрямую
+        write_line(
+            Level::ERROR,
+            &format!(
+                "Ошибка записи лога {}: {}, запись в файл отключена",
+                path.display(),
+                e
+            ),
+            "",
+        );
     }
 }
 
@@ -42,6 +286,57 @@ pub fn update_status(_ready: bool, _message: &str) {
 // заглушка
 }
 
+pub fn set_countdown(title: &str, deadline: &Deadline) {
+    *COUNTDOWN.lock().unwrap() = Some((title.to_string(), deadline.clone()));
+}
+
+// Текст обратного отсчета, пустой если даты нет или она уже наступила
+pub fn countdown() -> String {
+    let countdown = COUNTDOWN.lock().unwrap();
+    let Some((title, deadline)) = &*countdown else {
+        return String::new();
+    };
+    match deadline.remaining(SystemClock.now()) {
+        Some(remaining) if remaining.num_seconds() > 0 => format!(
+            "{} {} (Пекин): через {}",
+            title,
+            deadline,
+            format_remaining(remaining)
+        ),
+        _ => String::new(),
+    }
+}
+
+// Передает сообщения движка в tracing с их уровнем
+pub struct LoggerSink;
+
+impl EventSink for LoggerSink {
+    fn log(&self, message: &str) {
+        log(message);
+    }
+
+    fn event(&self, level: Level, message: &str) {
+        match level {
+            Level::ERROR => tracing::error!("{}", message),
+            Level::WARN => tracing::warn!("{}", message),
+            Level::INFO => tracing::info!("{}", message),
+            _ => tracing::debug!("{}", message),
+        }
+    }
+
+    fn status(&self, ready: bool, message: &str) {
+        update_status(ready, message);
+    }
+
+    fn deadline(&self, title: &str, deadline: &Deadline) {
+        set_countdown(title, deadline);
+    }
+
+    fn attempt(&self, record: &AttemptRecord) {
+        crate::history::record(record);
+    }
+}
+
 // Clear logs
 // pub fn clear() {
 //     if let Some(window_lock) = WINDOW.get() {
@@ -1,137 +1,149 @@
 // main.rs
 #![windows_subsystem = "windows"]
-mod deviceid;
+mod headless;
+mod history;
+mod logfile;
 mod logger;
-mod network;
+mod secrets;
+mod settings;
 
-use chrono::NaiveTime;
-use chrono_tz::Asia::Shanghai;
-use network::{
-    check_unlock_status, get_synchronized_beijing_time, wait_until_ping_time,
-    wait_until_target_time,
-};
-use reqwest::{Client, header::HeaderValue};
-use serde_json::Value;
+use micommunity_core::{Clock, SystemClock, deviceid};
 use slint::ComponentHandle;
 use std::{
-    collections::HashMap,
     error::Error,
+    sync::{Arc, Mutex},
 };
 
 slint::include_modules!();
 
 use logger::{log, update_status};
+use secrets::{SecretError, SecretStore};
+use settings::Settings;
 use tokio::spawn;
+use tracing::{error, warn};
 
-async fn on_submit(session: &Client, cookie_value: String, device_id: String) {
-    let cookie_value = cookie_value.clone();
-    if check_unlock_status(&session, cookie_value.as_str(), device_id.as_str()).await {
-        let start_beijing_time = network::get_initial_beijing_time().await;
-        if start_beijing_time.is_none() {
-            log("Ошибка получения начального времени".to_string());
-            return;
-        } else {
-            let start_timestamp = start_beijing_time.unwrap().timestamp();
-            let avg_ping =
-                wait_until_ping_time(start_beijing_time.unwrap(), start_timestamp).await;
-            wait_until_target_time(
-                start_beijing_time.unwrap(),
-                start_timestamp,
-                avg_ping as u64,
-            )
-            .await;
-            let url = "https://sgp-api.buy.mi.com/bbs/api/global/apply/bl-auth";
-            let mut headers = reqwest::header::HeaderMap::new();
-            headers.insert("Cookie", HeaderValue::from_str(format!("new_bbs_serviceToken={cookie_value};versionCode=500411;versionName=5.4.11;deviceId={device_id};").as_str()).unwrap());
-            headers.insert("User-Agent", HeaderValue::from_str("okhttp/4.9.3").unwrap());
-            headers.insert(
-                "Accept-Encoding",
-                HeaderValue::from_str("gzip, deflate, br").unwrap(),
-            );
-            headers.insert("Connection", HeaderValue::from_str("keep-alive").unwrap());
-
-            let request_time =
-                get_synchronized_beijing_time(start_beijing_time.unwrap(), start_timestamp)
-                    .await;
-            log(format!(
-                "Отправка запроса в {} (Пекинское время)",
-                request_time
-            ));
-            let response = session.post(url).headers(headers).send().await;
-            if response.is_err() {
-                log(format!(
-                    "Ошибка отправки запроса: {}",
-                    response.unwrap_err()
-                ));
-                return;
-            } else {
-                let response_time =
-                    get_synchronized_beijing_time(start_beijing_time.unwrap(), start_timestamp)
-                        .await;
-                log(format!(
-                    "Ответ получен в {} (Пекинское время)",
-                    response_time
-                ));
-                let response_data: HashMap<String, Value> =
-                    response.unwrap().json().await.unwrap();
-                let code = response_data.get("code").unwrap();
-                let data = response_data.get("data").unwrap();
-                if code == &Value::Number(0.into()) {
-                    let apply_result = data.get("apply_result").unwrap();
-                    let apply_result = apply_result.as_i64().unwrap();
-                    if apply_result == 1 {
-                        log("[Статус] Заявка одобрена, проверяем статус...");
-                        check_unlock_status(session, cookie_value.as_str(), device_id.as_str())
-                            .await;
-                    } else if apply_result == 3 {
-                        let deadline_format = data.get("deadline_format");
-                        log(format!("[Статус] Заявка не подана, исчерпан лимит (Попробуйте привзять телефон в настройках в стасут Mi Unlock), попробуйте снова в {} (Месяц/День).", if deadline_format.is_none() {"Не указано".to_string()} else { deadline_format.unwrap().as_str().unwrap().to_string() }));
-                        return
-                    } else if apply_result == 4 {
-                        let deadline_format = data.get("deadline_format");
-                        log(format!("[Статус] Заявка не подана, выдана блокировка на подачу заявки до {} (Месяц/День).", if deadline_format.is_none() {"Не указано".to_string()} else { deadline_format.unwrap().as_str().unwrap().to_string() }));                            
-                        let midnight_beijing = start_beijing_time.unwrap().date_naive().and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap());
-                        let midnight_beijing = midnight_beijing.and_utc().with_timezone(&Shanghai);
-                        let midnight_beijing = midnight_beijing + chrono::Duration::days(1);
-                        let response_time_beijing = response_time.with_timezone(&Shanghai);
-                        let time_diff = midnight_beijing - response_time_beijing;
-                        let time_diff = time_diff.num_milliseconds() as f64 / 1000 as f64;
-                        if time_diff <= 3.35 {
-                            log("Ваша заявка была принята, зайдите в настройки телефона для привязки");
-                            update_status(true, "Заявка принята");
-                        } else {
-                            log("Не удача, заявка подана слишком поздно");
-                            update_status(false, "Ошибка");
-                        }
-                    }
-                } else if code == &Value::Number(100001.into()) {
-                    log("[Статус] Заявка отклонена, ошибка запроса (code 100001).")
-                } else if code == &Value::Number(100003.into()) {
-                    log("[Статус] Возможно заявка одобрена, проверяем статус... (code 100003).");
-                    check_unlock_status(session, cookie_value.as_str(), device_id.as_str())
-                        .await;
-                }
-            }
+/// С `windows_subsystem = "windows"` у программы нет своей консоли: выводим
+/// справку, команды и лог `--headless` в консоль, из которой она запущена.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+    // Запуск не из консоли (ярлык) - подключаться не к чему, это не ошибка
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+/// Сохраненный токен `label`; ошибка чтения - как отсутствие токена.
+fn stored_token(store: &SecretStore, label: &str) -> String {
+    match store.get(label) {
+        Ok(token) => token.unwrap_or_default(),
+        Err(e) => {
+            error!("Ошибка чтения токена: {}", e);
+            String::new()
         }
-    } else {
-        log("[Статус] Ошибка, заявка отклонена или не подана.");
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // Окно не подключается к консоли: его лог остается в окне и файле
+    let cli = match headless::Cli::try_parse_args(std::env::args_os()) {
+        Ok(cli) => cli,
+        Err(e) => {
+            // Справка, версия и ошибки аргументов
+            attach_console();
+            e.exit();
+        }
+    };
+    if cli.headless || cli.command.is_some() {
+        attach_console();
+    }
+    // Вывод подкоманд идет в stdout, поэтому их лог - в stderr
+    logger::init(cli.log_level, cli.headless && cli.command.is_none());
+    match &cli.command {
+        Some(headless::Command::Token(command)) => {
+            std::process::exit(headless::run_token_command(&cli, command));
+        }
+        Some(headless::Command::DeviceId(command)) => {
+            std::process::exit(headless::run_device_id_command(&cli, command));
+        }
+        Some(headless::Command::History {
+            account,
+            limit,
+            json,
+        }) => {
+            std::process::exit(headless::run_history_command(
+                account.as_deref(),
+                *limit,
+                *json,
+            ));
+        }
+        None => {}
+    }
+    if cli.headless {
+        // Без окна: логгер пишет в stdout и файл, код выхода зависит от итога
+        let code = headless::run(cli).await;
+        std::process::exit(code);
+    }
+
     // Создаем окно
     let window = MainWindow::new()?;
 
     // Инициализируем логгер
-    logger::init(&window);
+    logger::init_window(&window);
+    logger::init_file();
 
     // Тест в основном потоке
     logger::log("Программа запустилась!");
 
     let weak_window = window.as_weak();
 
-    let session = Client::new();
+    // Настройки прошлого запуска
+    let mut settings = Settings::load();
+    let active_token = settings.active_token.clone();
+    let device_id = settings.ensure_device_id(&active_token);
+    if let Err(e) = settings.save() {
+        error!("Ошибка сохранения настроек: {}", e);
+    }
+
+    // Токен храним только в зашифрованном виде. Без системного хранилища
+    // ключей пароль файла токенов спрашиваем в отдельном окне
+    let passphrase_page = PassphrasePage::new()?;
+    let mut store_warning = String::new();
+    let store = match SecretStore::open(None) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            warn!("Токен не будет сохранен: {}", e);
+            store_warning = match e {
+                SecretError::NoPassphrase | SecretError::WrongPassphrase => {
+                    passphrase_page.show()?;
+                    "Токен не будет сохранен: введите пароль файла токенов".to_string()
+                }
+                e => format!("Токен не будет сохранен: {}", e),
+            };
+            window.invoke_show_error_input(store_warning.clone().into());
+            None
+        }
+    };
+    let saved_token = store
+        .as_ref()
+        .map(|store| stored_token(store, &settings.active_token))
+        .unwrap_or_default();
+    let store = Arc::new(Mutex::new(store));
+    window.set_token(saved_token.into());
+    window.set_deviceid(device_id.into());
+    window.set_agreement(settings.agreement);
+    window.set_all_accounts(settings.all_accounts);
+    window.set_daemon(settings.daemon);
+    window.set_log_level(logger::level_index(settings.log_level()) as i32);
+    logger::set_window_level(settings.log_level());
+    window.set_account_count(settings.token_labels.len() as i32);
+
+    let engine = history::engine(settings.config());
+    let settings = Arc::new(Mutex::new(settings));
 
     // Создаем окно AboutPage заранее, но не показываем
     let about = AboutPage::new()?;
@@ -161,29 +173,250 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
+    // Окно истории попыток: перечитывает файл при открытии и по кнопке
+    let history_page = HistoryPage::new()?;
+    history_page.on_refresh({
+        let history_weak = history_page.as_weak();
+        move || {
+            if let Some(history_page) = history_weak.upgrade() {
+                history_page.set_history(history::summary().into());
+            }
+        }
+    });
+    window.on_show_history({
+        let history_weak = history_page.as_weak();
+        move || {
+            if let Some(history_page) = history_weak.upgrade() {
+                history_page.set_history(history::summary().into());
+                history_page.show().unwrap();
+            }
+        }
+    });
+
+    // Токен шифруется и сохраняется только после завершения ввода. Пустое
+    // поле не удаляет сохраненный токен: для этого есть команда token remove.
+    // Ключ файла токенов вычисляется долго, поэтому сохраняем не в потоке окна
+    window.on_token_changed({
+        let weak_window = weak_window.clone();
+        let passphrase_weak = passphrase_page.as_weak();
+        let settings = settings.clone();
+        let store = store.clone();
+        move || {
+            let Some(window) = weak_window.upgrade() else {
+                return;
+            };
+            let token = window.get_token().trim().to_string();
+            if token.is_empty() {
+                return;
+            }
+            if store.lock().unwrap().is_none() {
+                window.invoke_show_error_input(store_warning.clone().into());
+                if let Some(passphrase_page) = passphrase_weak.upgrade() {
+                    passphrase_page.show().unwrap();
+                }
+                return;
+            }
+            let label = settings.lock().unwrap().active_token.clone();
+            let weak_window = weak_window.clone();
+            let settings = settings.clone();
+            let store = store.clone();
+            tokio::task::spawn_blocking(move || {
+                {
+                    let store = store.lock().unwrap();
+                    let Some(store) = &*store else {
+                        return;
+                    };
+                    if stored_token(store, &label) == token {
+                        return;
+                    }
+                    if let Err(e) = store.set(&label, &token) {
+                        error!("Ошибка сохранения токена: {}", e);
+                        return;
+                    }
+                }
+                let mut settings = settings.lock().unwrap();
+                settings.add_token_label(&label);
+                let account_count = settings.token_labels.len() as i32;
+                if let Err(e) = settings.save() {
+                    error!("Ошибка сохранения настроек: {}", e);
+                }
+                let _ = weak_window.upgrade_in_event_loop(move |window| {
+                    window.set_account_count(account_count);
+                });
+            });
+        }
+    });
+
+    // Пароль введен: открываем файл токенов, подставляем сохраненный токен или
+    // сохраняем уже введенный
+    passphrase_page.on_unlock({
+        let weak_window = weak_window.clone();
+        let passphrase_weak = passphrase_page.as_weak();
+        let settings = settings.clone();
+        let store = store.clone();
+        move |passphrase| {
+            let Some(passphrase_page) = passphrase_weak.upgrade() else {
+                return;
+            };
+            let opened = match SecretStore::open(Some(passphrase.to_string())) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    passphrase_page.invoke_show_error(e.to_string().into());
+                    return;
+                }
+            };
+            let saved_token = stored_token(&opened, &settings.lock().unwrap().active_token);
+            log(format!("Хранилище токенов открыто: {}", opened.describe()));
+            *store.lock().unwrap() = Some(opened);
+            passphrase_page.hide().unwrap();
+            if let Some(window) = weak_window.upgrade() {
+                if window.get_token().trim().is_empty() {
+                    window.set_token(saved_token.into());
+                } else {
+                    window.invoke_token_changed();
+                }
+            }
+        }
+    });
+
+    // deviceId проверяется и сохраняется после завершения ввода, а не на
+    // каждую набранную букву
+    window.on_deviceid_changed({
+        let weak_window = weak_window.clone();
+        let settings = settings.clone();
+        move || {
+            let Some(window) = weak_window.upgrade() else {
+                return;
+            };
+            let device_id = match deviceid::parse_device_id(&window.get_deviceid()) {
+                Ok(device_id) => device_id,
+                Err(e) => {
+                    window.invoke_show_error_deviceid(e.into());
+                    return;
+                }
+            };
+            let mut settings = settings.lock().unwrap();
+            let label = settings.active_token.clone();
+            if settings.device_id(&label) == Some(device_id.as_str()) {
+                return;
+            }
+            settings.set_device_id(&label, device_id);
+            if let Err(e) = settings.save() {
+                error!("Ошибка сохранения настроек: {}", e);
+            }
+        }
+    });
+
+    // Сохраняем остальные настройки при каждом изменении в окне
+    window.on_settings_changed({
+        let weak_window = weak_window.clone();
+        let settings = settings.clone();
+        move || {
+            let Some(window) = weak_window.upgrade() else {
+                return;
+            };
+            let mut settings = settings.lock().unwrap();
+            settings.agreement = window.get_agreement();
+            settings.all_accounts = window.get_all_accounts();
+            settings.daemon = window.get_daemon();
+            let log_level = logger::level_at(window.get_log_level());
+            if log_level != settings.log_level() {
+                logger::set_window_level(log_level);
+                settings.log_level = log_level.to_string();
+            }
+            window.set_account_count(settings.token_labels.len() as i32);
+            if let Err(e) = settings.save() {
+                error!("Ошибка сохранения настроек: {}", e);
+            }
+        }
+    });
+
+    // Явная смена deviceId: сервер увидит новое устройство
+    window.on_regenerate_deviceid({
+        let weak_window = weak_window.clone();
+        let settings = settings.clone();
+        move || {
+            let Some(window) = weak_window.upgrade() else {
+                return;
+            };
+            let mut settings = settings.lock().unwrap();
+            let label = settings.active_token.clone();
+            let device_id = deviceid::generate_device_id();
+            log(format!(
+                "Сгенерирован новый deviceId для \"{}\": {}",
+                label, device_id
+            ));
+            settings.set_device_id(&label, device_id.clone());
+            window.set_deviceid(device_id.into());
+            if let Err(e) = settings.save() {
+                error!("Ошибка сохранения настроек: {}", e);
+            }
+        }
+    });
+
     window.on_submit_request(move |cookie| {
         if let Some(window) = weak_window.upgrade() {
-            let cookie_value = cookie.to_string().trim().to_string();
-            let device_id = deviceid::generate_device_id();
+            if window.get_all_accounts() {
+                let store = store.lock().unwrap();
+                let Some(store) = &*store else {
+                    error!("Ошибка: хранилище токенов недоступно, очередь аккаунтов невозможна");
+                    return;
+                };
+                let accounts = {
+                    let mut settings = settings.lock().unwrap();
+                    let labels = settings.token_labels.clone();
+                    settings.saved_accounts(store, &labels)
+                };
+                if accounts.is_empty() {
+                    error!("Ошибка: нет сохраненных аккаунтов");
+                    return;
+                }
+
+                let engine = engine.clone();
+                let weak_window = weak_window.clone();
+                window.set_account_results("Подача заявок...".into());
+                spawn(async move {
+                    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+                    let results = engine.submit_all(&clock, &accounts).await;
+                    let summary = results
+                        .iter()
+                        .map(|result| format!("{}: {}", result.label, result.outcome))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    let _ = weak_window.upgrade_in_event_loop(move |window| {
+                        window.set_account_results(summary.into());
+                    });
+                });
+                return;
+            }
 
-            window.set_deviceid(device_id.clone().into());
+            let cookie_value = cookie.to_string().trim().to_string();
+            let device_id = match deviceid::parse_device_id(&window.get_deviceid()) {
+                Ok(device_id) => device_id,
+                Err(e) => {
+                    error!("Ошибка: {}", e);
+                    window.invoke_show_error_deviceid(e.into());
+                    return;
+                }
+            };
 
             if cookie_value.is_empty() {
-                log("Ошибка: Cookie пустой".to_string());
+                error!("Ошибка: Cookie пустой");
                 return;
             }
 
-            let session_clone = session.clone();
-            let cookie_value_clone = cookie_value.clone();
-            let device_id_clone = device_id.clone();
+            let label = settings.lock().unwrap().active_token.clone();
+            let engine = engine.clone().with_account(&label);
+            let daemon = window.get_daemon();
 
             spawn(async move {
-                on_submit(
-                    &session_clone,
-                    cookie_value_clone.to_string(),
-                    device_id_clone,
-                )
-                .await;
+                if daemon {
+                    engine
+                        .run_daemon(&SystemClock, &cookie_value, &device_id)
+                        .await;
+                } else {
+                    engine.submit(&SystemClock, &cookie_value, &device_id).await;
+                }
             });
         }
     });
@@ -192,14 +425,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Обработчик для гиперссылки в AboutPage
     if let Some(about) = about_weak.upgrade() {
         about.on_hyperlink(move |url| {
-            if let Err(e) = open::that(url.to_string()) {
-                log(format!("Ошибка открытия URL: {}; {:#?}", url, e));
+            if let Err(e) = open::that(url.as_str()) {
+                error!("Ошибка открытия URL: {}; {:#?}", url, e);
             };
         });
     }
 
     window.on_exit(|| std::process::exit(0));
 
+    // Обратный отсчет до блокировки/лимита/срока одобрения
+    let countdown_timer = slint::Timer::default();
+    let weak_window = window.as_weak();
+    countdown_timer.start(
+        slint::TimerMode::Repeated,
+        std::time::Duration::from_secs(1),
+        move || {
+            if let Some(window) = weak_window.upgrade() {
+                window.set_countdown(logger::countdown().into());
+            }
+        },
+    );
+
     window.run()?;
     Ok(())
 }
@@ -0,0 +1,362 @@
+// secrets.rs
+// Хранилище serviceToken: системное хранилище ключей (Windows Credential
+// Manager, macOS Keychain, Secret Service), а если его нет - файл,
+// зашифрованный ключом из пароля (Argon2id + XChaCha20-Poly1305).
+use std::{collections::BTreeMap, fmt, fs, io, path::PathBuf};
+
+use argon2::Argon2;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chacha20poly1305::{
+    Key, XChaCha20Poly1305, XNonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+
+const KEYRING_SERVICE: &str = "micommunity";
+const SECRETS_FILE: &str = "tokens.enc";
+const SALT_LEN: usize = 16;
+
+/// Переменная окружения с паролем от зашифрованного файла.
+pub const PASSPHRASE_ENV: &str = "MI_TOKEN_PASSPHRASE";
+
+#[derive(Debug)]
+pub enum SecretError {
+    Keyring(keyring::Error),
+    Io(io::Error),
+    /// Файл поврежден или неверный формат.
+    Format(String),
+    /// Неверный пароль (или файл изменен).
+    WrongPassphrase,
+    /// Хранилища ключей нет, а пароль не задан.
+    NoPassphrase,
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::Keyring(e) => write!(f, "ошибка хранилища ключей: {}", e),
+            SecretError::Io(e) => write!(f, "ошибка файла токенов: {}", e),
+            SecretError::Format(e) => write!(f, "файл токенов поврежден: {}", e),
+            SecretError::WrongPassphrase => write!(f, "неверный пароль файла токенов"),
+            SecretError::NoPassphrase => write!(
+                f,
+                "системное хранилище ключей недоступно, задайте пароль в {}",
+                PASSPHRASE_ENV
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+impl From<io::Error> for SecretError {
+    fn from(e: io::Error) -> Self {
+        SecretError::Io(e)
+    }
+}
+
+impl From<keyring::Error> for SecretError {
+    fn from(e: keyring::Error) -> Self {
+        SecretError::Keyring(e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+enum Backend {
+    Keyring,
+    File { path: PathBuf, passphrase: String },
+}
+
+pub struct SecretStore {
+    backend: Backend,
+}
+
+/// Токен для показа: первые и последние 4 символа.
+pub fn mask(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() <= 12 {
+        return "*".repeat(chars.len().max(4));
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+fn keyring_available() -> bool {
+    let Ok(entry) = keyring::Entry::new(KEYRING_SERVICE, "__probe__") else {
+        return false;
+    };
+    matches!(entry.get_password(), Ok(_) | Err(keyring::Error::NoEntry))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], SecretError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| SecretError::Format(e.to_string()))?;
+    Ok(key)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, SecretError> {
+    BASE64
+        .decode(value)
+        .map_err(|e| SecretError::Format(e.to_string()))
+}
+
+impl SecretStore {
+    /// Открывает системное хранилище ключей, а если оно недоступно - файл
+    /// с паролем `passphrase` (или из [`PASSPHRASE_ENV`]).
+    pub fn open(passphrase: Option<String>) -> Result<SecretStore, SecretError> {
+        if keyring_available() {
+            return Ok(SecretStore {
+                backend: Backend::Keyring,
+            });
+        }
+        let passphrase = passphrase
+            .or_else(|| std::env::var(PASSPHRASE_ENV).ok())
+            .filter(|p| !p.is_empty())
+            .ok_or(SecretError::NoPassphrase)?;
+        let path = dirs::config_dir()
+            .ok_or_else(|| SecretError::Format("папка конфигурации не найдена".to_string()))?
+            .join("micommunity")
+            .join(SECRETS_FILE);
+        let store = SecretStore {
+            backend: Backend::File { path, passphrase },
+        };
+        // Проверяем пароль сразу, а не при первом чтении
+        store.read_file()?;
+        Ok(store)
+    }
+
+    /// Где хранятся токены (для лога).
+    pub fn describe(&self) -> &'static str {
+        match self.backend {
+            Backend::Keyring => "системное хранилище ключей",
+            Backend::File { .. } => "зашифрованный файл",
+        }
+    }
+
+    pub fn get(&self, label: &str) -> Result<Option<String>, SecretError> {
+        match &self.backend {
+            Backend::Keyring => match keyring::Entry::new(KEYRING_SERVICE, label)?.get_password() {
+                Ok(token) => Ok(Some(token)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Backend::File { .. } => Ok(self.read_file()?.remove(label)),
+        }
+    }
+
+    pub fn set(&self, label: &str, token: &str) -> Result<(), SecretError> {
+        match &self.backend {
+            Backend::Keyring => {
+                keyring::Entry::new(KEYRING_SERVICE, label)?.set_password(token)?;
+                Ok(())
+            }
+            Backend::File { .. } => {
+                let mut tokens = self.read_file()?;
+                tokens.insert(label.to_string(), token.to_string());
+                self.write_file(&tokens)
+            }
+        }
+    }
+
+    pub fn remove(&self, label: &str) -> Result<(), SecretError> {
+        match &self.backend {
+            Backend::Keyring => {
+                match keyring::Entry::new(KEYRING_SERVICE, label)?.delete_credential() {
+                    Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Backend::File { .. } => {
+                let mut tokens = self.read_file()?;
+                tokens.remove(label);
+                self.write_file(&tokens)
+            }
+        }
+    }
+
+    fn read_file(&self) -> Result<BTreeMap<String, String>, SecretError> {
+        let Backend::File { path, passphrase } = &self.backend else {
+            return Ok(BTreeMap::new());
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let file: EncryptedFile =
+            serde_json::from_str(&text).map_err(|e| SecretError::Format(e.to_string()))?;
+        let key = derive_key(passphrase, &decode(&file.salt)?)?;
+        let nonce = decode(&file.nonce)?;
+        if nonce.len() != 24 {
+            return Err(SecretError::Format("неверная длина nonce".to_string()));
+        }
+        let plaintext = XChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(XNonce::from_slice(&nonce), decode(&file.data)?.as_slice())
+            .map_err(|_| SecretError::WrongPassphrase)?;
+        serde_json::from_slice(&plaintext).map_err(|e| SecretError::Format(e.to_string()))
+    }
+
+    fn write_file(&self, tokens: &BTreeMap<String, String>) -> Result<(), SecretError> {
+        let Backend::File { path, passphrase } = &self.backend else {
+            return Ok(());
+        };
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext =
+            serde_json::to_vec(tokens).map_err(|e| SecretError::Format(e.to_string()))?;
+        let data = XChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| SecretError::Format("ошибка шифрования".to_string()))?;
+        let file = EncryptedFile {
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            data: BASE64.encode(data),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(&file).map_err(io::Error::other)?;
+        let tmp = path.with_extension("enc.tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_store(name: &str, passphrase: &str) -> SecretStore {
+        let dir = std::env::temp_dir().join(format!("micommunity-secrets-{}", std::process::id()));
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        SecretStore {
+            backend: Backend::File {
+                path,
+                passphrase: passphrase.to_string(),
+            },
+        }
+    }
+
+    fn path(store: &SecretStore) -> &PathBuf {
+        match &store.backend {
+            Backend::File { path, .. } => path,
+            Backend::Keyring => unreachable!(),
+        }
+    }
+
+    /// Хранилище с тем же файлом и другим паролем.
+    fn reopen(store: &SecretStore, passphrase: &str) -> SecretStore {
+        SecretStore {
+            backend: Backend::File {
+                path: path(store).clone(),
+                passphrase: passphrase.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn file_roundtrip() {
+        let store = file_store("roundtrip.enc", "пароль");
+        assert_eq!(store.get("main").unwrap(), None);
+
+        store.set("main", "token-main").unwrap();
+        store.set("spare", "token-spare").unwrap();
+        let reopened = reopen(&store, "пароль");
+        assert_eq!(reopened.get("main").unwrap().as_deref(), Some("token-main"));
+        assert_eq!(
+            reopened.get("spare").unwrap().as_deref(),
+            Some("token-spare")
+        );
+
+        // Токен не лежит в файле в открытом виде
+        let text = fs::read_to_string(path(&store)).unwrap();
+        assert!(!text.contains("token-main"));
+
+        store.remove("main").unwrap();
+        assert_eq!(reopened.get("main").unwrap(), None);
+        assert_eq!(
+            reopened.get("spare").unwrap().as_deref(),
+            Some("token-spare")
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let store = file_store("passphrase.enc", "верный");
+        store.set("main", "token-main").unwrap();
+        assert!(matches!(
+            reopen(&store, "неверный").get("main"),
+            Err(SecretError::WrongPassphrase)
+        ));
+        // Запись с неверным паролем не затирает файл
+        assert!(reopen(&store, "неверный").set("main", "other").is_err());
+        assert_eq!(store.get("main").unwrap().as_deref(), Some("token-main"));
+    }
+
+    #[test]
+    fn tampered_file_is_rejected() {
+        let store = file_store("tampered.enc", "пароль");
+        store.set("main", "token-main").unwrap();
+        let text = fs::read_to_string(path(&store)).unwrap();
+        let mut file: EncryptedFile = serde_json::from_str(&text).unwrap();
+        let mut data = decode(&file.data).unwrap();
+        data[0] ^= 1;
+        file.data = BASE64.encode(data);
+        fs::write(path(&store), serde_json::to_string(&file).unwrap()).unwrap();
+
+        assert!(matches!(
+            store.get("main"),
+            Err(SecretError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let store = file_store("truncated.enc", "пароль");
+        store.set("main", "token-main").unwrap();
+        let text = fs::read_to_string(path(&store)).unwrap();
+
+        fs::write(path(&store), &text[..text.len() / 2]).unwrap();
+        assert!(matches!(store.get("main"), Err(SecretError::Format(_))));
+
+        // Обрезанный nonce
+        let mut file: EncryptedFile = serde_json::from_str(&text).unwrap();
+        file.nonce = BASE64.encode([0u8; 12]);
+        fs::write(path(&store), serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(store.get("main"), Err(SecretError::Format(_))));
+
+        // Обрезанные данные не проходят проверку подлинности
+        let mut file: EncryptedFile = serde_json::from_str(&text).unwrap();
+        let data = decode(&file.data).unwrap();
+        file.data = BASE64.encode(&data[..data.len() - 4]);
+        fs::write(path(&store), serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(
+            store.get("main"),
+            Err(SecretError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn mask_hides_the_middle() {
+        assert_eq!(mask(""), "****");
+        assert_eq!(mask("abc"), "****");
+        assert_eq!(mask("abcdefghijkl"), "************");
+        assert_eq!(mask("abcdefghijklm"), "abcd…jklm");
+        // Режется по символам, а не по байтам
+        assert_eq!(mask("токен-из-кириллицы"), "токе…лицы");
+    }
+}
@@ -0,0 +1,222 @@
+// settings.rs
+// Настройки между запусками: названия токенов, deviceId, параметры движка
+// и интерфейса. Хранятся в JSON в папке конфигурации пользователя.
+use std::{collections::BTreeMap, fs, io, path::PathBuf, time::Duration};
+
+use micommunity_core::{
+    Account, ApiConfig, BurstConfig, Config, NtpConfig, RetryConfig, ScheduleConfig,
+    StrategyConfig, deviceid,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{Level, error, warn};
+
+use crate::logger::log;
+use crate::secrets::SecretStore;
+
+const APP_DIR: &str = "micommunity";
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Название токена, если пользователь не задал свое.
+pub const DEFAULT_TOKEN_LABEL: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Названия сохраненных токенов (сами токены - в [`SecretStore`]).
+    pub token_labels: Vec<String>,
+    /// Токен, который используют окно и запуск без окна.
+    pub active_token: String,
+    /// deviceId каждого аккаунта (по названию токена). Сервер видит одно и то же
+    /// устройство при каждой заявке.
+    pub device_ids: BTreeMap<String, String>,
+    /// Галочка подтверждения риска.
+    pub agreement: bool,
+    /// Подавать заявку для всех сохраненных аккаунтов, а не только активного.
+    pub all_accounts: bool,
+    /// Повторять заявку каждую ночь, пока она не одобрена.
+    pub daemon: bool,
+    pub api_base_url: String,
+    pub ntp_servers: Vec<String>,
+    pub ntp_timeout_ms: u64,
+    pub ntp_min_servers: usize,
+    pub ntp_max_disagreement_ms: u64,
+    pub ntp_resync_interval_secs: u64,
+    pub burst_count: usize,
+    pub burst_interval_ms: u64,
+    /// Стратегия в строковом виде [`StrategyConfig`].
+    pub strategy: String,
+    pub measure_before_secs: u64,
+    pub accept_window_ms: u64,
+    pub retry_max_days: u32,
+    pub retry_wake_before_secs: u64,
+    /// Минимальный уровень сообщений в окне лога (`DEBUG`, `INFO`, `WARN`, `ERROR`).
+    pub log_level: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let config = Config::default();
+        Settings {
+            token_labels: vec![],
+            active_token: DEFAULT_TOKEN_LABEL.to_string(),
+            device_ids: BTreeMap::new(),
+            agreement: false,
+            all_accounts: false,
+            daemon: false,
+            api_base_url: config.api.base_url,
+            ntp_servers: config.ntp.servers,
+            ntp_timeout_ms: config.ntp.timeout.as_millis() as u64,
+            ntp_min_servers: config.ntp.min_servers,
+            ntp_max_disagreement_ms: config.ntp.max_disagreement.as_millis() as u64,
+            ntp_resync_interval_secs: config.ntp.resync_interval.as_secs(),
+            burst_count: config.burst.count,
+            burst_interval_ms: config.burst.interval.as_millis() as u64,
+            strategy: config.schedule.strategy.to_string(),
+            measure_before_secs: config.schedule.measure_before.as_secs(),
+            accept_window_ms: config.schedule.accept_window.as_millis() as u64,
+            retry_max_days: config.retry.max_days,
+            retry_wake_before_secs: config.retry.wake_before.as_secs(),
+            log_level: Level::INFO.to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// Путь к файлу настроек (`<config dir>/micommunity/settings.json`).
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_DIR).join(SETTINGS_FILE))
+    }
+
+    /// Загружает настройки. Если файла нет или он поврежден - настройки по умолчанию.
+    pub fn load() -> Settings {
+        let Some(path) = Settings::path() else {
+            warn!("Папка конфигурации не найдена, настройки не сохраняются");
+            return Settings::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(settings) => settings,
+                Err(e) => {
+                    error!("Ошибка чтения настроек {}: {}", path.display(), e);
+                    Settings::default()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                error!("Ошибка чтения настроек {}: {}", path.display(), e);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = Settings::path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        // Пишем через временный файл, чтобы не оставить половину настроек
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &path)
+    }
+
+    pub fn add_token_label(&mut self, label: &str) {
+        if !self.token_labels.iter().any(|l| l == label) {
+            self.token_labels.push(label.to_string());
+        }
+    }
+
+    pub fn remove_token_label(&mut self, label: &str) {
+        self.token_labels.retain(|l| l != label);
+    }
+
+    /// Сохраненный deviceId аккаунта `label`.
+    pub fn device_id(&self, label: &str) -> Option<&str> {
+        self.device_ids.get(label).map(|id| id.as_str())
+    }
+
+    pub fn set_device_id(&mut self, label: &str, device_id: String) {
+        self.device_ids.insert(label.to_string(), device_id);
+    }
+
+    /// deviceId аккаунта `label`; при первом обращении генерируется и запоминается
+    /// (не сохраняется на диск - это делает вызывающий).
+    pub fn ensure_device_id(&mut self, label: &str) -> String {
+        if let Some(device_id) = self.device_id(label) {
+            return device_id.to_string();
+        }
+        let device_id = deviceid::generate_device_id();
+        log(format!("Сгенерирован deviceId для \"{}\": {}", label, device_id));
+        self.set_device_id(label, device_id.clone());
+        device_id
+    }
+
+    /// Аккаунты очереди из хранилища токенов: токен и deviceId по названию.
+    /// Новые deviceId сразу сохраняются в настройки.
+    pub fn saved_accounts(&mut self, store: &SecretStore, labels: &[String]) -> Vec<Account> {
+        let mut accounts = vec![];
+        for label in labels {
+            match store.get(label) {
+                Ok(Some(token)) => accounts.push(Account {
+                    label: label.clone(),
+                    cookie_value: token.trim().to_string(),
+                    device_id: self.ensure_device_id(label),
+                }),
+                Ok(None) => warn!("Токен \"{}\" не найден, аккаунт пропущен", label),
+                Err(e) => error!(
+                    "Ошибка чтения токена \"{}\": {}, аккаунт пропущен",
+                    label, e
+                ),
+            }
+        }
+        if let Err(e) = self.save() {
+            error!("Ошибка сохранения настроек: {}", e);
+        }
+        accounts
+    }
+
+    /// Уровень окна лога; некорректное значение - `INFO`.
+    pub fn log_level(&self) -> Level {
+        self.log_level.parse().unwrap_or(Level::INFO)
+    }
+
+    /// Настройки движка.
+    pub fn config(&self) -> Config {
+        let strategy = self.strategy.parse().unwrap_or_else(|e| {
+            warn!(
+                "Некорректная стратегия в настройках ({}), используется {}",
+                e,
+                StrategyConfig::default()
+            );
+            StrategyConfig::default()
+        });
+        Config {
+            api: ApiConfig {
+                base_url: self.api_base_url.clone(),
+            },
+            ntp: NtpConfig {
+                servers: self.ntp_servers.clone(),
+                timeout: Duration::from_millis(self.ntp_timeout_ms),
+                min_servers: self.ntp_min_servers,
+                max_disagreement: Duration::from_millis(self.ntp_max_disagreement_ms),
+                resync_interval: Duration::from_secs(self.ntp_resync_interval_secs),
+            },
+            burst: BurstConfig {
+                count: self.burst_count,
+                interval: Duration::from_millis(self.burst_interval_ms),
+            },
+            schedule: ScheduleConfig {
+                strategy,
+                measure_before: Duration::from_secs(self.measure_before_secs),
+                accept_window: Duration::from_millis(self.accept_window_ms),
+            },
+            retry: RetryConfig {
+                max_days: self.retry_max_days,
+                wake_before: Duration::from_secs(self.retry_wake_before_secs),
+            },
+        }
+    }
+}